@@ -1,4 +1,4 @@
-//@ known-bug: rust-lang/rust#123887
+//@ known-bug: #123887
 //@ compile-flags: -Clink-dead-code
 
 #![feature(extern_types)]
@@ -1,4 +1,4 @@
-//@ known-bug: rust-lang/rust#125014
+//@ known-bug: #125014
 //@ compile-flags: -Znext-solver=coherence
 #![feature(specialization)]
 
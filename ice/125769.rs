@@ -1,4 +1,4 @@
-//@ known-bug: rust-lang/rust#125769
+//@ known-bug: #125769
 
 #![feature(generic_const_exprs)]
 
@@ -1,4 +1,4 @@
-//@ known-bug: rust-lang/rust#125772
+//@ known-bug: #125772
 //@ only-64bit
 #![feature(generic_const_exprs)]
 
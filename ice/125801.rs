@@ -1,4 +1,4 @@
-//@ known-bug: rust-lang/rust#125801
+//@ known-bug: #125801
 
 #![feature(generic_const_exprs)]
 #![allow(incomplete_features)]
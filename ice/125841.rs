@@ -1,4 +1,4 @@
-//@ known-bug: rust-lang/rust#125841
+//@ known-bug: #125841
 #![feature(non_lifetime_binders)]
 fn take(id: impl for<T> Fn(T) -> T) {
     id(0);
@@ -1,4 +1,4 @@
-//@ known-bug: rust-lang/rust#126667
+//@ known-bug: #126667
 #![warn(rust_2021_compatibility)]
 
 trait Static<'a> {}
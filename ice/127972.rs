@@ -1,4 +1,4 @@
-//@ known-bug: #127962
+//@ known-bug: #127972
 #![feature(generic_const_exprs, const_arg_path)]
 
 fn zero_init<const usize: usize>() -> Substs1<{ (N) }> {
@@ -1,4 +1,4 @@
-//@ known-bug: rust-lang/rust#130104
+//@ known-bug: #130104
 
 fn main() {
     let non_secure_function =
@@ -1,4 +1,4 @@
-//@ known-bug: rust-lang/rust#130310
+//@ known-bug: #130310
 
 use std::marker::PhantomData;
 
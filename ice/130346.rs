@@ -1,4 +1,4 @@
-//@ known-bug: rust-lang/rust#130346
+//@ known-bug: #130346
 
 #![feature(non_lifetime_binders)]
 #![allow(unused)]
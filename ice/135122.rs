@@ -1,3 +1,4 @@
+//@ known-bug: #135122
 trait Add {
     type Output;
     fn add(_: (), _: Self::Output) {}
@@ -1,3 +1,4 @@
+//@ known-bug: #141014
 #![feature(min_generic_const_args)]
 
 trait Abc {}
@@ -1,3 +1,4 @@
+//@ known-bug: #141504
 #![feature(closure_lifetime_binder)]
 
 fn fails() {
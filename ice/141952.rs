@@ -1,3 +1,4 @@
+//@ known-bug: #141952
 #![allow(incomplete_features)]
 #![feature(ergonomic_clones)]
 
@@ -1,3 +1,4 @@
+//@ known-bug: #142717
 #![feature(inherent_associated_types)]
 struct Foo<T>(T);
 
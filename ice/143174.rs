@@ -1,3 +1,4 @@
+//@ known-bug: #143174
 type Fun = unsafe extern "C" fn();
 
 struct Foo(Fun);
@@ -1,3 +1,4 @@
+//@ known-bug: #143896
 pub trait TraitA<'a> {
     const K: u8 = 0;
 }
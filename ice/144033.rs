@@ -1,3 +1,4 @@
+//@ known-bug: #144033
 trait FooMut {
     fn bar<I>(self, _: I)
     where
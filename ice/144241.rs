@@ -1,3 +1,4 @@
+//@ known-bug: #144241
 fn main() {
     let _: &mut (dyn ?Sized + !Send) = &mut {};
 }
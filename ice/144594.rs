@@ -1,3 +1,4 @@
+//@ known-bug: #144594
 reuse a as b {
     || {
         use std::ops::Add;
@@ -1,3 +1,4 @@
+//@ known-bug: #145824
 #![cfg_attr(not(test), no_std)]
 #![feature(generic_const_exprs)]
 #![feature(associated_const_equality)]
@@ -1,3 +1,4 @@
+//@ known-bug: #146353
 #![feature(trait_alias)]
 
 use std::mem::{MaybeUninit};
@@ -1,3 +1,4 @@
+//@ known-bug: #146754
 unsafe extern "C" {
     async fn function() -> [(); || {}];
 }
\ No newline at end of file
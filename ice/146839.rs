@@ -1,3 +1,4 @@
+//@ known-bug: #146839
 #![feature(coroutines, stmt_expr_attributes)]
 
 const _: for<'a> fn() -> i32 = #[coroutine] || -> i32 { yield 0; return 1; };
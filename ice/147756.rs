@@ -1,3 +1,4 @@
+//@ known-bug: #147756
 fn array() {
     #[rustc_layout_scalar_valid_range_start(1)]
     struct NonZero<T>([T; 4]);
@@ -1,3 +1,4 @@
+//@ known-bug: #148121
 pub trait Super<X> {
     type X;
 }
@@ -1,3 +1,4 @@
+//@ known-bug: #148283
 use std::ops::Deref;
 
 struct A;
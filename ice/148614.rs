@@ -1,3 +1,4 @@
+//@ known-bug: #148614
 #[allow(dead_code)]
 use std::arch::global_asm;
 
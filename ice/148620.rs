@@ -1,3 +1,4 @@
+//@ known-bug: #148620
 #![feature(associated_const_equality)]
 
 trait Trait<'a> {
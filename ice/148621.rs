@@ -1,3 +1,4 @@
+//@ known-bug: #148621
 #![feature(type_alias_impl_trait)]
 
 type Foo<V> = impl std::fmt::Debug;
@@ -1,3 +1,4 @@
+//@ known-bug: #148625
 #![feature(associated_const_equality)]
 
 trait Project {
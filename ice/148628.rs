@@ -1,3 +1,4 @@
+//@ known-bug: #148628
 use std::arch::asm;
 
 static TEST: i32 = 0;
@@ -1,3 +1,4 @@
+//@ known-bug: #148630
 #![feature(unboxed_closures)]
 
 use std::future::Future;
@@ -1,3 +1,4 @@
+//@ known-bug: #148631
 struct C;
 
 struct S<T>(Option<T>);
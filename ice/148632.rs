@@ -1,3 +1,4 @@
+//@ known-bug: #148632
 #![feature(associated_const_equality)]
 
 trait B<C> {}
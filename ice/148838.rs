@@ -1,3 +1,4 @@
+//@ known-bug: #148838
 #![feature(min_generic_const_args)]
 #![feature(generic_const_exprs)]
 struct Both<const is_123: u32 = 3, T> {
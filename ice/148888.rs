@@ -1,3 +1,4 @@
+//@ known-bug: #148888
 #![feature(type_alias_impl_trait)]
 
 type Foo = impl Send;
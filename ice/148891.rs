@@ -1,3 +1,4 @@
+//@ known-bug: #148891
 pub struct Char3(pub [i8; 3]);
 
 macro_rules! values {
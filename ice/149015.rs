@@ -1,3 +1,4 @@
+//@ known-bug: #149015
 trait Trait1 {
     type Assoc;
 }
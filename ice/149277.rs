@@ -1,3 +1,4 @@
+//@ known-bug: #149277
 pub trait PointeeSized {}
 
 pub trait PointeeSizedTr: PointeeSized {}
@@ -0,0 +1,171 @@
+//! On-disk cache of `rustc` output.
+//!
+//! Entries live at `<dir>/<commit hash>/<key>.json`, where the key is a hash
+//! of the source and the flags passed to `rustc`. The path of the source file
+//! is deliberately not part of the key, so entries survive `drice dedup`
+//! moving files around (though paths in cached diagnostics may be stale).
+//!
+//! For a `rustc` given by path, e.g. a local stage1 build, the directory also
+//! has a hash of its path and modification time, since rebuilding it doesn't
+//! change the commit hash.
+
+use std::{
+    env, fs,
+    hash::{DefaultHasher, Hash as _, Hasher as _},
+    io::Write as _,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use tracing::{debug, warn};
+
+use crate::rustc::{Exit, Output, Toolchain};
+
+#[derive(Debug, Clone)]
+pub(crate) struct Cache {
+    dir: PathBuf,
+}
+
+impl Cache {
+    #[must_use]
+    pub(crate) fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    /// `$XDG_CACHE_HOME/drice`, or `~/.cache/drice`
+    #[must_use]
+    pub(crate) fn default_dir() -> Option<PathBuf> {
+        env::var_os("XDG_CACHE_HOME")
+            .filter(|d| !d.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| env::home_dir().map(|h| h.join(".cache")))
+            .map(|d| d.join("drice"))
+    }
+
+    #[must_use]
+    pub(crate) fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub(crate) fn clear(&self) -> anyhow::Result<()> {
+        if self.dir.exists() {
+            fs::remove_dir_all(&self.dir).with_context(|| {
+                format!("failed to remove cache directory: {}", self.dir.display())
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Key for a source file compiled with the given flags
+    #[must_use]
+    pub(crate) fn key(source: &str, args: &[String]) -> String {
+        // `DefaultHasher` isn't guaranteed to be stable across Rust releases,
+        // but the worst case is a cache miss.
+        let mut hasher = DefaultHasher::new();
+        source.hash(&mut hasher);
+        args.hash(&mut hasher);
+        format!("{:016x}", hasher.finish())
+    }
+
+    fn entry(&self, toolchain: &Toolchain, key: &str) -> Option<PathBuf> {
+        let Some(commit) = toolchain.cached_version()?.commit_hash else {
+            debug!("not caching, commit hash of rustc ({toolchain}) is unknown");
+            return None;
+        };
+        let dir = match toolchain {
+            Toolchain::Rustup(_) => commit,
+            // Local builds are often rebuilt without a new commit
+            Toolchain::Path(path) => format!("{commit}-{}", Self::stamp(path)?),
+        };
+        Some(self.dir.join(dir).join(format!("{key}.json")))
+    }
+
+    /// Hash of the path and modification time of a `rustc` binary
+    fn stamp(path: &Path) -> Option<String> {
+        let stamp = fs::canonicalize(path).and_then(|path| {
+            let modified = fs::metadata(&path)?.modified()?;
+            Ok((path, modified))
+        });
+        match stamp {
+            Ok(stamp) => {
+                let mut hasher = DefaultHasher::new();
+                stamp.hash(&mut hasher);
+                Some(format!("{:016x}", hasher.finish()))
+            }
+            Err(e) => {
+                debug!(
+                    "not caching, failed to stat rustc ({}): {e}",
+                    path.display()
+                );
+                None
+            }
+        }
+    }
+
+    #[must_use]
+    pub(crate) fn get(&self, toolchain: &Toolchain, key: &str) -> Option<Output> {
+        let path = self.entry(toolchain, key)?;
+        let content = fs::read_to_string(&path).ok()?;
+        match serde_json::from_str(&content) {
+            Ok(output) => {
+                debug!("cache hit: {}", path.display());
+                Some(output)
+            }
+            Err(e) => {
+                warn!("ignoring malformed cache entry {}: {e}", path.display());
+                None
+            }
+        }
+    }
+
+    pub(crate) fn put(
+        &self,
+        toolchain: &Toolchain,
+        key: &str,
+        output: &Output,
+    ) -> anyhow::Result<()> {
+        // Results that depend on resource limits aren't reproducible
+        if output.exit == Exit::Timeout || output.out_of_memory() {
+            return Ok(());
+        }
+        let Some(path) = self.entry(toolchain, key) else {
+            return Ok(());
+        };
+        let dir = path.parent().expect("entries are in a directory");
+        fs::create_dir_all(dir)
+            .with_context(|| format!("failed to create directory: {}", dir.display()))?;
+        // Write to a temporary file and rename for atomicity with `--jobs`
+        let mut temp =
+            tempfile::NamedTempFile::new_in(dir).context("failed to create temporary file")?;
+        serde_json::to_writer(&mut temp, output).context("failed to serialize rustc output")?;
+        temp.flush().context("failed to flush temporary file")?;
+        temp.persist(&path)
+            .with_context(|| format!("failed to write cache entry: {}", path.display()))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        time::{Duration, SystemTime},
+    };
+
+    use super::Cache;
+
+    #[test]
+    fn test_stamp() {
+        let dir = tempfile::tempdir().unwrap();
+        let rustc = dir.path().join("rustc");
+        assert_eq!(Cache::stamp(&rustc), None);
+        let file = fs::File::create(&rustc).unwrap();
+        file.set_modified(SystemTime::UNIX_EPOCH).unwrap();
+        let before = Cache::stamp(&rustc).unwrap();
+        assert_eq!(Cache::stamp(&rustc), Some(before.clone()));
+        // Rebuilt
+        file.set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(1))
+            .unwrap();
+        assert_ne!(Cache::stamp(&rustc), Some(before));
+    }
+}
@@ -1,13 +1,36 @@
-use std::{fs, path::PathBuf};
+use std::{
+    collections::HashMap,
+    fmt::Write as _,
+    fs,
+    path::{Path, PathBuf},
+    sync::LazyLock,
+};
 
 use anyhow::Context;
-use tracing::debug;
-
-use crate::rustc;
+use tracing::{debug, warn};
+
+use crate::{
+    database::{self, Dup, Entry},
+    explain,
+    index::SignatureIndex,
+    meta::{self, MatchRules},
+    normalize, par, project,
+    report::{IceKind, IceReport},
+    rustc,
+    similarity::Similarity,
+};
 
 #[derive(Debug)]
 pub(crate) struct CheckConfig {
-    pub(crate) file: PathBuf,
+    pub(crate) files: Vec<PathBuf>,
+    pub(crate) rustc: rustc::RustcConfig,
+    pub(crate) jobs: usize,
+    /// Number of closest known ICEs to list, see [`ranked`]
+    pub(crate) top: usize,
+    /// Explain how each ICE compares to a known ICE, see [`explain`]
+    pub(crate) explain: bool,
+    /// The known ICE to explain against, rather than the closest one
+    pub(crate) against: Option<String>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -22,20 +45,26 @@ pub enum IceStatus {
     NotAnIce,
     UsesInternalFeatures,
     DuplicateOfExisting(&'static str),
+    /// Not a known ICE, but the same as a retired one: possibly a regression
+    MatchesFixed(&'static str),
     AppearsNew(IceType),
+    /// rustc did not finish before the timeout
+    Hang,
+    OutOfMemory,
+}
+
+impl From<IceKind> for IceType {
+    fn from(kind: IceKind) -> Self {
+        match kind {
+            IceKind::Segfault => Self::Segfault,
+            IceKind::Panic | IceKind::Bug | IceKind::DelayedBug => Self::Other,
+        }
+    }
 }
 
 #[must_use]
 pub(crate) fn is_ice(out: &str) -> Option<IceType> {
-    if out.contains("error: rustc interrupted by SIGSEGV, printing backtrace") {
-        Some(IceType::Segfault)
-    } else if out.contains("error: internal compiler error:")
-        || out.contains("error: the compiler unexpectedly panicked")
-    {
-        Some(IceType::Other)
-    } else {
-        None
-    }
+    IceKind::detect(out).map(IceType::from)
 }
 
 #[must_use]
@@ -88,118 +117,366 @@ fn uses_internal_features(out: &str) -> bool {
     false
 }
 
-pub(crate) fn same(l: &str, r: &str) -> bool {
-    if l == r {
+/// The query stack without names from the program, which are between backticks
+pub(crate) fn query_stack_shape(report: &IceReport) -> Option<Vec<(&str, String)>> {
+    let stack = report.query_stack.as_ref()?;
+    Some(
+        stack
+            .iter()
+            .map(|frame| {
+                let desc = &frame.description;
+                let shape = match (desc.find('`'), desc.rfind('`')) {
+                    (Some(first), Some(last)) => format!("{}`{}", &desc[..first], &desc[last..]),
+                    _ => desc.clone(),
+                };
+                (frame.query.as_str(), shape)
+            })
+            .collect(),
+    )
+}
+
+/// Whether two ICEs originated in the same place.
+///
+/// Lines move as the source of `rustc` changes, so the same kind of ICE in
+/// the same file, function and caller also counts as the same place.
+pub(crate) fn same_origin(l: &IceReport, r: &IceReport) -> bool {
+    let (Some(l_loc), Some(r_loc)) = (&l.location, &r.location) else {
+        return false;
+    };
+    if l_loc.source_path() != r_loc.source_path() {
+        return false;
+    }
+    if (l_loc.line, l_loc.column) == (r_loc.line, r_loc.column) {
         return true;
     }
-    let l_path = extract_file_path(l);
-    let r_path = extract_file_path(r);
-    let l_msg = extract_message(l);
-    let r_msg = extract_message(r);
-    let l_stack = extract_query_stack(l);
-    let r_stack = extract_query_stack(r);
-    debug!("{l_path:?}");
-    debug!("{r_path:?}");
-    debug!("{}", l_path == r_path);
-    debug!("{l_msg:?}");
-    debug!("{r_msg:?}");
-    debug!("{}", l_msg == r_msg);
+    if l.kind != r.kind {
+        return false;
+    }
+    let l_fns = l.functions();
+    let r_fns = r.functions();
+    debug!("{l_fns:?}");
+    debug!("{r_fns:?}");
+    l_fns.len() >= 2 && r_fns.len() >= 2 && l_fns[..2] == r_fns[..2]
+}
+
+/// Whether two ICEs of the same kind went through the same functions in
+/// `rustc`, see [`IceReport::signature`]
+pub(crate) fn same_signature(l: &IceReport, r: &IceReport) -> bool {
+    if l.kind != r.kind {
+        return false;
+    }
+    let l_sig = l.signature();
+    let r_sig = r.signature();
+    debug!("{l_sig:?}");
+    debug!("{r_sig:?}");
+    !l_sig.is_empty() && l_sig == r_sig
+}
+
+/// Whether two ICEs are the same, judging by where they originated, their
+/// backtrace (without a location or query stack, e.g. for segfaults), their
+/// query stack and the shape of their normalized message, with the `rules`
+/// of the entry that `r` is from
+pub(crate) fn same_report(l: &IceReport, r: &IceReport, rules: &MatchRules) -> bool {
+    debug!("{:?}", l.location);
+    debug!("{:?}", r.location);
+    debug!("{:?}", l.message);
+    debug!("{:?}", r.message);
+    let l_stack = query_stack_shape(l);
+    let r_stack = query_stack_shape(r);
     debug!("{l_stack:?}");
     debug!("{r_stack:?}");
-    debug!("{}", l_stack == r_stack);
-    if l_path.is_some() && l_path == r_path && l_stack == r_stack {
-        if let Some(l_msg) = l_msg
-            && let Some(r_msg) = r_msg
-            && let Some(l_fst) = l_msg.split_ascii_whitespace().next()
-            && let Some(r_fst) = r_msg.split_ascii_whitespace().next()
-        {
-            // TODO: Replace with a better (string distance?) check
-            if !l_fst.starts_with('`')
-                && !l_fst.starts_with('[')
-                && !r_fst.starts_with('`')
-                && !r_fst.starts_with('[')
-                && l_fst != r_fst
-            {
-                return false;
-            }
-        }
+    if !rules.ignore_query_stack && l_stack != r_stack {
+        return false;
+    }
+    let origin = if l.location.is_some() && r.location.is_some() {
+        same_origin(l, r)
+    } else {
+        same_signature(l, r)
+    };
+    // Without a query stack, the backtrace has to corroborate the location
+    if !origin || (l_stack.is_none() && !same_signature(l, r)) {
+        return false;
+    }
+    if let Some(re) = &rules.message {
+        return l.message.as_deref().is_some_and(|m| re.is_match(m));
+    }
+    if let Some(l_msg) = l.normalized_message()
+        && let Some(r_msg) = r.normalized_message()
+    {
+        let l_shape = normalize::shape(&l_msg);
+        let r_shape = normalize::shape(&r_msg);
+        debug!("{l_shape:?}");
+        debug!("{r_shape:?}");
+        return l_shape == r_shape;
+    }
+    true
+}
+
+/// Whether each ICE in the output `l` is one of the ICEs in the output `r`,
+/// given their ICEs (see [`IceReport::parse_all`]) and the rules for `r`
+pub(crate) fn same_parsed(
+    l: &str,
+    l_reports: &[IceReport],
+    r: &str,
+    r_reports: &[IceReport],
+    rules: &MatchRules,
+) -> bool {
+    if l == r {
         return true;
     }
-    false
+    !l_reports.is_empty()
+        && l_reports
+            .iter()
+            .all(|l| r_reports.iter().any(|r| same_report(l, r, rules)))
 }
 
-pub(crate) fn extract_file_path(s: &str) -> Option<String> {
-    for line in s.lines() {
-        if line.starts_with("thread 'rustc'")
-            && let Some(idx) = line.find("panicked at ")
-        {
-            let idx = idx + "panicked at ".len();
-            return Some(line[idx..line.len() - 1].to_owned());
-        }
-        if let Some(idx) = line.find("note: delayed at ") {
-            let idx = idx + "note: delayed at ".len();
-            return Some(line[idx..line.len() - 1].to_owned());
+/// A known ICE, with the ICEs in its output and those of its alternate
+/// reproducers
+#[derive(Debug)]
+struct Known {
+    name: &'static str,
+    stderr: &'static str,
+    /// The alternate reproducers
+    dups: Vec<Dup>,
+    reports: Vec<IceReport>,
+}
+
+impl Known {
+    fn new(entry: Entry) -> Self {
+        Self {
+            name: entry.name,
+            stderr: entry.stderr,
+            reports: [entry.stderr]
+                .into_iter()
+                .chain(entry.dups.iter().map(|dup| dup.stderr))
+                .flat_map(IceReport::parse_all)
+                .collect(),
+            dups: entry.dups,
         }
     }
-    None
 }
 
-pub(crate) fn extract_message(s: &str) -> Option<String> {
-    let mut is_next = false;
-    for line in s.lines() {
-        if is_next {
-            return Some(line.to_owned());
-        }
-        if let Some(line) = line.strip_prefix("error: internal compiler error: ") {
-            let first = &line[0..line.find(' ').unwrap_or(line.len())];
-            if first.contains('/') && first.contains(':') {
-                return Some(line[first.len() + 1..].to_owned());
-            }
-            return Some(line.to_owned());
-        }
-        if line.starts_with("thread 'rustc'") && line.contains("panicked at ") {
-            is_next = true;
+/// A known ICE without metadata nor duplicates, for tests
+#[cfg(test)]
+fn known_ice(name: &'static str, stderr: &'static str) -> Known {
+    Known::new(Entry {
+        name,
+        stderr,
+        meta: None,
+        dups: Vec::new(),
+    })
+}
+
+/// The known ICEs, parsed once
+static KNOWN: LazyLock<Vec<Known>> =
+    LazyLock::new(|| database::entries().into_iter().map(Known::new).collect());
+
+/// The retired ICEs, see [`database::retired`]
+static FIXED: LazyLock<Vec<Known>> =
+    LazyLock::new(|| database::retired().into_iter().map(Known::new).collect());
+
+/// The ICEs of `known`, by (index of the known ICE, index of the ICE)
+fn signatures(known: &[Known]) -> SignatureIndex<(usize, usize)> {
+    let mut index = SignatureIndex::default();
+    for (i, known) in known.iter().enumerate() {
+        let rules = &meta::get(known.name).rules;
+        for (j, report) in known.reports.iter().enumerate() {
+            index.insert((i, j), report, rules.ignore_query_stack);
         }
     }
-    None
+    index
 }
 
-pub(crate) fn extract_query_stack(s: &str) -> Option<String> {
-    let lines: Vec<&str> = s.lines().collect();
-    let start_idx = lines
-        .iter()
-        .position(|line| line.trim() == "query stack during panic:")?;
-    let end_idx = lines[start_idx..]
-        .iter()
-        .position(|line| line.trim() == "end of query stack")?;
-    let mut out = String::with_capacity(end_idx);
-    for line in lines[start_idx..=start_idx + end_idx].iter().copied() {
-        // exclude names from program
-        if let Some(first) = line.find('`')
-            && let Some(last) = line.rfind('`')
+/// The ICEs of [`KNOWN`], see [`signatures`]
+static INDEX: LazyLock<SignatureIndex<(usize, usize)>> = LazyLock::new(|| signatures(&KNOWN));
+
+/// The known ICEs by their output
+static BY_STDERR: LazyLock<HashMap<&str, &str>> = LazyLock::new(|| {
+    let mut by_stderr = HashMap::new();
+    for known in KNOWN.iter() {
+        for stderr in [known.stderr]
+            .into_iter()
+            .chain(known.dups.iter().map(|dup| dup.stderr))
         {
-            out.push_str(&line[..first]);
-            out.push('`');
-            out.push_str(&line[last..]);
-        } else {
-            out.push_str(line);
+            by_stderr.entry(stderr).or_insert(known.name);
         }
-        out.push('\n');
     }
-    Some(out)
+    by_stderr
+});
+
+/// The known ICE that `report` is a duplicate of, the most similar one if
+/// several
+fn known(report: &IceReport) -> Option<&'static str> {
+    known_in(report, &KNOWN, &INDEX)
+}
+
+/// [`known`], among the ICEs `known` with the [`signatures`] `index`
+fn known_in(
+    report: &IceReport,
+    known: &[Known],
+    index: &SignatureIndex<(usize, usize)>,
+) -> Option<&'static str> {
+    index
+        .candidates(report)
+        .into_iter()
+        .filter_map(|(i, j)| {
+            let known = &known[i];
+            let r = &known.reports[j];
+            same_report(report, r, &meta::get(known.name).rules)
+                .then(|| (i, Similarity::new(report, r).score()))
+        })
+        .fold(None, |best: Option<(usize, f64)>, (i, score)| match best {
+            Some((_, best_score)) if best_score >= score => best,
+            _ => Some((i, score)),
+        })
+        .map(|(i, _)| known[i].name)
 }
 
+/// Each ICE in `s`, with the known ICE that it's a duplicate of (if any)
+pub(crate) fn occurrences(s: &str) -> Vec<(IceReport, Option<&'static str>)> {
+    IceReport::parse_all(s)
+        .into_iter()
+        .map(|report| {
+            let known = known(&report);
+            (report, known)
+        })
+        .collect()
+}
+
+/// The known ICE that `s` is a duplicate of, that of its first ICE, or `None`
+/// if any of its ICEs appears new
 pub(crate) fn exists(s: &str) -> Option<&'static str> {
-    for (nm, stderr) in crate::ice::ICES.iter().copied() {
-        if same(s, stderr) {
-            return Some(nm);
-        }
+    if let Some(name) = BY_STDERR.get(s) {
+        return Some(name);
     }
-    None
+    exists_in(s, &KNOWN, &INDEX)
+}
+
+/// [`exists`], among the ICEs `known` with the [`signatures`] `index`
+fn exists_in(
+    s: &str,
+    known: &[Known],
+    index: &SignatureIndex<(usize, usize)>,
+) -> Option<&'static str> {
+    let dups = IceReport::parse_all(s)
+        .iter()
+        .map(|report| known_in(report, known, index))
+        .collect::<Option<Vec<_>>>()?;
+    dups.first().copied()
+}
+
+/// The retired ICE in `fixed` that `s` is the same as, if any
+fn fixed(s: &str, fixed: &[Known]) -> Option<&'static str> {
+    let reports = IceReport::parse_all(s);
+    fixed
+        .iter()
+        .find(|known| {
+            let rules = &meta::get(known.name).rules;
+            same_parsed(s, &reports, known.stderr, &known.reports, rules)
+        })
+        .map(|known| known.name)
+}
+
+/// The known ICEs ranked by similarity to `report`, most similar first
+fn ranked(report: &IceReport) -> Vec<(&'static str, Similarity)> {
+    let mut ranked: Vec<_> = KNOWN
+        .iter()
+        .filter_map(|known| {
+            let similarity = known
+                .reports
+                .iter()
+                .map(|r| Similarity::new(report, r))
+                .max_by(|l, r| l.score().total_cmp(&r.score()))?;
+            Some((known.name, similarity))
+        })
+        .collect();
+    // Stable, so ties stay in database order
+    ranked.sort_by(|(_, l), (_, r)| r.score().total_cmp(&l.score()));
+    ranked
+}
+
+/// The known ICE `against`: the one with that path, or else the only one
+/// with that name, like `123` for `ice/123.rs`
+fn find<'a>(known: &'a [Known], against: &str) -> anyhow::Result<&'a Known> {
+    if let Some(known) = known.iter().find(|known| {
+        database::identity(Path::new(known.name)) == database::identity(Path::new(against))
+    }) {
+        return Ok(known);
+    }
+    let named: Vec<_> = known
+        .iter()
+        .filter(|known| meta::name(known.name) == meta::name(against))
+        .collect();
+    match named[..] {
+        [] => anyhow::bail!("no such ICE in the database: {against}"),
+        [known] => Ok(known),
+        _ => anyhow::bail!(
+            "ambiguous ICE {against}, pass its path: {}",
+            named
+                .iter()
+                .map(|known| known.name)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    }
+}
+
+/// Explain each ICE in `s` against the known ICE `against` (see [`find`]), or
+/// else the one it's a duplicate of or is closest to
+fn explain_all(s: &str, against: Option<&str>) -> anyhow::Result<String> {
+    let against = match against {
+        Some(against) => Some(find(&KNOWN, against)?),
+        None => None,
+    };
+    let mut result = String::new();
+    for (i, (report, dup)) in occurrences(s).iter().enumerate() {
+        let score = |r: &IceReport| Similarity::new(report, r).score();
+        let closest = |known: &'static Known| {
+            known
+                .reports
+                .iter()
+                .max_by(|l, r| score(l).total_cmp(&score(r)))
+        };
+        let known = against
+            .or_else(|| dup.and_then(|nm| KNOWN.iter().find(|k| k.name == nm)))
+            .or_else(|| {
+                KNOWN
+                    .iter()
+                    .filter_map(|k| Some((k, score(closest(k)?))))
+                    .max_by(|(_, l), (_, r)| l.total_cmp(r))
+                    .map(|(k, _)| k)
+            });
+        let Some(known) = known else {
+            continue;
+        };
+        let rules = &meta::get(known.name).rules;
+        // The ICE of the entry that matches, or else the most similar one
+        let Some(r) = known
+            .reports
+            .iter()
+            .find(|r| same_report(report, r, rules))
+            .or_else(|| closest(known))
+        else {
+            continue;
+        };
+        let _ = write!(
+            result,
+            "\n  ICE {} against {}:\n{}",
+            i + 1,
+            known.name,
+            explain::explain(report, r, rules)
+        );
+    }
+    Ok(result)
 }
 
 #[must_use]
 pub fn analyze_ice(output: &str) -> IceStatus {
+    analyze_with(output, &FIXED)
+}
+
+/// [`analyze_ice`], with the retired ICEs `retired`
+fn analyze_with(output: &str, retired: &[Known]) -> IceStatus {
     if let Some(ice_type) = is_ice(output) {
         if uses_internal_features(output) {
             return IceStatus::UsesInternalFeatures;
@@ -207,57 +484,314 @@ pub fn analyze_ice(output: &str) -> IceStatus {
         if let Some(existing) = exists(output) {
             return IceStatus::DuplicateOfExisting(existing);
         }
+        if let Some(fixed) = fixed(output, retired) {
+            return IceStatus::MatchesFixed(fixed);
+        }
         return IceStatus::AppearsNew(ice_type);
     }
     IceStatus::NotAnIce
 }
 
-pub(crate) fn check(config: CheckConfig) -> anyhow::Result<()> {
-    let p = format!("{}", config.file.display());
-    let mut s = fs::read_to_string(config.file.as_path())
-        .with_context(|| format!("failed to read file: {}", config.file.display()))?;
-    if p.ends_with(".rs") {
-        if let Some(feat) = code_uses_internal_features(s.as_str()) {
-            eprintln!("{p}: skipping, uses internal feature(s) `{feat}`");
-            return Ok(());
-        }
-        s = rustc::go(config.file.as_path())
-            .with_context(|| format!("failed to run rustc on file: {}", config.file.display()))?;
+#[must_use]
+pub(crate) fn analyze_output(output: &rustc::Output) -> IceStatus {
+    analyze_output_with(output, &FIXED)
+}
+
+/// [`analyze_output`], with the retired ICEs `retired`
+fn analyze_output_with(output: &rustc::Output, retired: &[Known]) -> IceStatus {
+    if output.exit == rustc::Exit::Timeout {
+        return IceStatus::Hang;
+    }
+    if output.out_of_memory() {
+        return IceStatus::OutOfMemory;
     }
-    match analyze_ice(s.as_str()) {
-        IceStatus::NotAnIce => {
-            eprintln!("{p}: not an ICE");
+    analyze_with(&output.text(), retired)
+}
+
+/// Check a single file, returning a description of the result
+fn check_file(config: &CheckConfig, file: &Path) -> anyhow::Result<String> {
+    let rustc = &config.rustc;
+    let mut p = format!("{}", file.display());
+    let (status, invocation, text) = if project::is_reproducer(file) {
+        let s = project::sources(file)?;
+        if let Some(feat) = code_uses_internal_features(s.as_str()) {
+            return Ok(format!("{p}: skipping, uses internal feature(s) `{feat}`"));
         }
-        IceStatus::UsesInternalFeatures => {
-            eprintln!("{p}: skipping, uses internal features");
+        let output =
+            rustc::go(rustc, file).with_context(|| format!("failed to run rustc on: {p}"))?;
+        if let Some(krate) = &output.krate {
+            let _ = write!(p, " (crate `{krate}`)");
         }
+        let text = output.text().into_owned();
+        (analyze_output(&output), Some(output.invocation()), text)
+    } else {
+        let s = fs::read_to_string(file).with_context(|| format!("failed to read file: {p}"))?;
+        (analyze_ice(s.as_str()), None, s)
+    };
+    let mut result = match status {
+        IceStatus::NotAnIce => format!("{p}: not an ICE"),
+        IceStatus::UsesInternalFeatures => format!("{p}: skipping, uses internal features"),
         IceStatus::DuplicateOfExisting(existing) => {
-            eprintln!("{p}: duplicate of {existing}");
-        }
-        IceStatus::AppearsNew(IceType::Segfault) => {
-            eprintln!("{p}: appears new! (segfault)");
+            let mut report = format!("{p}: duplicate of {existing}");
+            let out = Path::new(existing).with_extension("out");
+            match rustc::Invocation::read(&out) {
+                Ok(Some(recorded)) => {
+                    let _ = write!(report, "\n  recorded: {recorded}");
+                }
+                Ok(None) => {}
+                Err(e) => warn!("{e:#}"),
+            }
+            if let Some(current) = invocation {
+                let _ = write!(report, "\n  current:  {current}");
+            }
+            for line in meta::get(existing).describe(existing) {
+                let _ = write!(report, "\n  {line}");
+            }
+            let dups = KNOWN
+                .iter()
+                .find(|k| k.name == existing)
+                .map_or(&[][..], |k| k.dups.as_slice());
+            for dup in dups {
+                let _ = write!(report, "\n  also reproduced by {}", dup.name);
+                if let Some(provenance) = &meta::get(dup.name).provenance {
+                    let _ = write!(report, ", from {provenance}");
+                }
+            }
+            for other in KNOWN.iter().map(|k| k.name) {
+                if let Some(relation) = meta::relation(existing, other) {
+                    let _ = write!(report, "\n  {relation} {other}");
+                }
+            }
+            report
         }
+        IceStatus::MatchesFixed(fixed) => match meta::get(fixed).issue(fixed) {
+            Some(issue) => {
+                format!("{p}: matches fixed issue #{issue} ({fixed}) — possible regression")
+            }
+            None => format!("{p}: matches fixed {fixed} — possible regression"),
+        },
+        IceStatus::AppearsNew(IceType::Segfault) => format!("{p}: appears new! (segfault)"),
         IceStatus::AppearsNew(IceType::Other) => {
-            eprintln!("{p}: appears new! (not a segfault)");
+            format!("{p}: appears new! (not a segfault)")
+        }
+        IceStatus::Hang => format!("{p}: hang (rustc timed out)"),
+        IceStatus::OutOfMemory => format!("{p}: rustc ran out of memory"),
+    };
+    let is_ice = matches!(
+        status,
+        IceStatus::DuplicateOfExisting(_) | IceStatus::MatchesFixed(_) | IceStatus::AppearsNew(_)
+    );
+    let occurrences = if is_ice {
+        occurrences(&text)
+    } else {
+        Vec::new()
+    };
+    if occurrences.len() > 1 {
+        for (i, (report, known)) in occurrences.iter().enumerate() {
+            let location = report.location.as_ref().map_or_else(
+                || String::from("(no path)"),
+                |l| format!("{}:{}:{}", l.source_path(), l.line, l.column),
+            );
+            let _ = match known {
+                Some(known) => write!(
+                    result,
+                    "\n  ICE {} at {location}: duplicate of {known}",
+                    i + 1
+                ),
+                // All ICEs match the retired one, see `fixed`
+                None if let IceStatus::MatchesFixed(fixed) = status => write!(
+                    result,
+                    "\n  ICE {} at {location}: matches fixed {fixed}",
+                    i + 1
+                ),
+                None => write!(result, "\n  ICE {} at {location}: appears new!", i + 1),
+            };
+        }
+    }
+    if config.top > 0 && is_ice {
+        let reports = IceReport::parse_all(&text);
+        for (i, report) in reports.iter().enumerate() {
+            let indent = if reports.len() > 1 {
+                let _ = write!(result, "\n  ICE {}:", i + 1);
+                "    "
+            } else {
+                "  "
+            };
+            for (nm, similarity) in ranked(report).into_iter().take(config.top) {
+                let _ = write!(result, "\n{indent}{nm}: {similarity}");
+            }
         }
     }
-    Ok(())
+    if config.explain && is_ice {
+        result.push_str(&explain_all(&text, config.against.as_deref())?);
+    }
+    Ok(result)
+}
+
+pub(crate) fn check(config: CheckConfig) -> anyhow::Result<()> {
+    par::for_each_ordered(
+        &config.files,
+        config.jobs,
+        |file| check_file(&config, file),
+        |_, result| {
+            eprintln!("{}", result?);
+            Ok(())
+        },
+    )
 }
 
 #[cfg(test)]
 mod tests {
-    use super::same;
-    use crate::{check::extract_file_path, ice::ICES};
+    use super::{
+        INDEX, IceStatus, KNOWN, analyze_output_with, analyze_with, exists_in, find, fixed,
+        known_ice, same_parsed, same_report, signatures,
+    };
+    use crate::meta::{self, MatchRules};
+    use crate::{
+        database::dup_original,
+        ice::ICES,
+        report::IceReport,
+        rustc::{self, Exit},
+    };
+
+    fn same(l: &str, r: &str) -> bool {
+        same_with(l, r, &MatchRules::default())
+    }
+
+    fn same_with(l: &str, r: &str, rules: &MatchRules) -> bool {
+        same_parsed(
+            l,
+            &IceReport::parse_all(l),
+            r,
+            &IceReport::parse_all(r),
+            rules,
+        )
+    }
+
+    const FOLD: &str = "\
+thread 'rustc' panicked at /rustc-dev/e50aa6fba/compiler/rustc_middle/src/ty/fold.rs:237:33:
+escaping bound vars in `foo`
+stack backtrace:
+   0: std::panicking::begin_panic::<rustc_errors::ExplicitBug>
+   1: rustc_middle::util::bug::bug_fmt
+   2: <rustc_middle::ty::fold::BoundVarReplacer<_>>::try_fold_ty
+   3: <rustc_middle::ty::context::TyCtxt>::instantiate_bound_regions_uncached
+   4: rustc_hir_typeck::closure::sig_of_closure
+
+error: the compiler unexpectedly panicked. this is a bug.
+
+query stack during panic:
+#0 [typeck] type-checking `foo`
+end of query stack
+";
 
     #[test]
-    fn test_extract_file_path() {
-        assert_eq!(extract_file_path("foo").as_deref(), None);
-        assert_eq!(
-            extract_file_path(
-                "thread 'rustc' (843541) panicked at compiler/rustc_hir_analysis/src/hir_ty_lowering/dyn_trait.rs:425:17:"
-            ).as_deref(),
-            Some("compiler/rustc_hir_analysis/src/hir_ty_lowering/dyn_trait.rs:425:17")
+    fn test_same_line_drift() {
+        let drifted = FOLD
+            .replace("/rustc-dev/", "/rustc/")
+            .replace("fold.rs:237:33", "fold.rs:251:17");
+        assert!(same(FOLD, &drifted));
+        let moved = FOLD.replace("fold.rs:237:33", "mod.rs:237:33");
+        assert!(!same(FOLD, &moved));
+        // Elsewhere in the same file
+        let elsewhere = drifted.replace(
+            "   2: <rustc_middle::ty::fold::BoundVarReplacer<_>>::try_fold_ty\n",
+            "",
         );
+        assert!(!same(FOLD, &elsewhere));
+    }
+
+    /// A panic followed by a delayed bug
+    const PANIC_AND_DELAYED: &str = "\
+thread 'rustc' panicked at compiler/rustc_drice/src/panic.rs:12:34:
+index out of bounds: the len is 2 but the index is 2
+stack backtrace:
+   0: core::panicking::panic_bounds_check
+   1: rustc_drice::panic::check
+   2: rustc_drice::panic::run
+
+error: the compiler unexpectedly panicked. this is a bug.
+
+query stack during panic:
+#0 [check_well_formed] checking that `foo` is well-formed
+end of query stack
+note: no errors encountered even though delayed bugs were created
+
+note: those delayed bugs will now be shown as internal compiler errors
+
+error: internal compiler error: wfcheck without an existing error
+  --> ice/1.rs:2:5
+   |
+2  |     fn foo() {}
+   |     ^^^^^^^^
+   |
+note: delayed at /rustc-dev/e50aa6fba/compiler/rustc_drice/src/delayed.rs:28:10
+         0: <rustc_errors::DiagCtxtHandle>::span_delayed_bug::<rustc_span::span_encoding::Span, &str>
+         1: rustc_drice::delayed::check
+         2: rustc_drice::delayed::run
+
+error: aborting due to 1 previous error
+";
+
+    #[test]
+    fn test_exists_every_ice() {
+        assert_eq!(IceReport::parse_all(PANIC_AND_DELAYED).len(), 2);
+        let known = [known_ice("ice/1.rs", PANIC_AND_DELAYED)];
+        let index = signatures(&known);
+        let exists = |s: &str| exists_in(s, &known, &index);
+        assert_eq!(exists(&format!("{PANIC_AND_DELAYED}\n")), Some("ice/1.rs"));
+        // Only the panic
+        let (panic, delayed) = PANIC_AND_DELAYED
+            .split_once("note: no errors encountered")
+            .unwrap();
+        assert_eq!(exists(panic), Some("ice/1.rs"));
+        // Another delayed bug
+        let other = delayed.replace("delayed.rs:28:10", "other.rs:28:10");
+        let other = format!("{panic}note: no errors encountered{other}");
+        assert_eq!(exists(&other), None);
+    }
+
+    #[test]
+    fn test_index_finds_every_match() {
+        for (_, content) in ICES {
+            for report in IceReport::parse_all(content) {
+                let candidates = INDEX.candidates(&report);
+                for (i, known) in KNOWN.iter().enumerate() {
+                    let rules = &meta::get(known.name).rules;
+                    for (j, r) in known.reports.iter().enumerate() {
+                        assert!(
+                            !same_report(&report, r, rules) || candidates.contains(&(i, j)),
+                            "{} isn't a candidate for its match",
+                            known.name
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    const SEGFAULT: &str = "\
+error: rustc interrupted by SIGSEGV, printing backtrace
+
+/root/.rustup/toolchains/nightly/lib/librustc_driver-b5a965480ee51f9a.so(+0x3c8e78c)[0x7f40d0a8e78c]
+/lib/x86_64-linux-gnu/libc.so.6(+0x3c050)[0x7f40ccc5a050]
+/root/.rustup/toolchains/nightly/lib/librustc_driver-b5a965480ee51f9a.so(+0x56d88d7)[0x7f40d24d88d7]
+
+### cycle encountered after 3 frames with period 2
+/root/.rustup/toolchains/nightly/lib/librustc_driver-b5a965480ee51f9a.so(+0x56da858)[0x7f40d24da858]
+/root/.rustup/toolchains/nightly/lib/librustc_driver-b5a965480ee51f9a.so(+0x56d8915)[0x7f40d24d8915]
+### recursed 63 times
+
+note: rustc unexpectedly overflowed its stack! this is a bug
+";
+
+    #[test]
+    fn test_same_segfault() {
+        // Addresses change from run to run, offsets don't
+        let relocated = SEGFAULT.replace(")[0x7f", ")[0x7e");
+        assert!(same(SEGFAULT, &relocated));
+        let other = SEGFAULT.replace("(+0x56d88d7)", "(+0x16d88d7)");
+        assert!(!same(SEGFAULT, &other));
     }
 
     #[test]
@@ -273,29 +807,8 @@ mod tests {
         for (i, (nm0, content1)) in ICES.iter().copied().enumerate() {
             for (j, (nm1, content2)) in ICES.iter().skip(i + 1).copied().enumerate() {
                 if i != j {
-                    if nm0 == "ice/122529.rs" && nm1 == "ice/141124.rs" {
-                        continue; // TODO: Are these dups?
-                    }
-                    if nm0 == "ice/127643.rs" && nm1 == "ice/131046.rs" {
-                        continue; // TODO these are maybe dups?
-                    }
-                    if nm0 == "ice/127643.rs" && nm1 == "ice/131406.rs" {
-                        continue; // TODO these are maybe dups?
-                    }
-                    if nm0 == "ice/131046.rs" && nm1 == "ice/131406.rs" {
-                        continue; // TODO these are maybe dups?
-                    }
-                    if nm0 == "ice/135845.rs" && nm1 == "ice/139738.rs" {
-                        continue; // TODO: Are these dups?
-                    }
-                    if nm0 == "ice/139738.rs" && nm1 == "ice/141504.rs" {
-                        continue; // See below
-                    }
-                    if nm0 == "ice/135845.rs" && nm1 == "ice/141504.rs" {
-                        continue; // See latter, subtle semantic distinction
-                    }
-                    if nm0 == "ice/123959.rs" && nm1 == "ice/98322.rs" {
-                        continue; // TODO?
+                    if meta::relation(nm0, nm1).is_some() {
+                        continue;
                     }
 
                     assert!(
@@ -326,23 +839,21 @@ mod tests {
 
             if path.extension().and_then(|s| s.to_str()) == Some("out") {
                 let file_name = path.file_name().unwrap().to_str().unwrap();
+                let name = path.file_stem().unwrap().to_str().unwrap();
 
                 let dup_path = dup_dir.join(file_name);
-                let ice_path = ice_dir.join(file_name);
+                let ice_path = ice_dir.join(format!("{}.out", dup_original(name)));
 
                 let dup_content = fs::read_to_string(&dup_path)
                     .unwrap_or_else(|_| panic!("Failed to read {}", dup_path.display()));
                 let ice_content = fs::read_to_string(&ice_path)
                     .unwrap_or_else(|_| panic!("Failed to read {}", ice_path.display()));
 
-                if file_name == "144241.out" {
-                    // different query stacks, but same line
-                    continue;
-                }
-
+                let rules = &meta::get(&ice_path.with_extension("rs").to_string_lossy()).rules;
                 assert!(
-                    same(&dup_content, &ice_content),
-                    "File ice/dup/{file_name} should be a duplicate of ice/{file_name}",
+                    same_with(&dup_content, &ice_content, rules),
+                    "File ice/dup/{file_name} should be a duplicate of {}",
+                    ice_path.display(),
                 );
             }
         }
@@ -374,4 +885,84 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_find() {
+        let known: Vec<_> = ["ice/1.rs", "ice/2.rs", "private/2.rs"]
+            .into_iter()
+            .map(|name| known_ice(name, ""))
+            .collect();
+        assert_eq!(find(&known, "1").unwrap().name, "ice/1.rs");
+        assert_eq!(find(&known, "./ice/2.rs").unwrap().name, "ice/2.rs");
+        assert_eq!(find(&known, "private/2.rs").unwrap().name, "private/2.rs");
+        assert!(find(&known, "2").is_err());
+        assert!(find(&known, "3").is_err());
+    }
+
+    /// An ICE unlike any in the database
+    const RETIRED: &str = "\
+thread 'rustc' panicked at compiler/rustc_drice/src/retired.rs:12:34:
+retired ICE in `foo`
+stack backtrace:
+   0: rustc_drice::retired::check
+   1: rustc_drice::retired::run
+
+error: the compiler unexpectedly panicked. this is a bug.
+
+query stack during panic:
+#0 [type_of] computing type of `foo`
+end of query stack
+";
+
+    #[test]
+    fn test_analyze_output() {
+        let output = |stderr, exit, memory_limit| rustc::Output {
+            exit,
+            memory_limit,
+            ..rustc::output(stderr)
+        };
+        let retired = [known_ice("ice/attic/1.rs", RETIRED)];
+        let status = |stderr, exit| analyze_output_with(&output(stderr, exit, None), &retired);
+        let error = "error[E0425]: cannot find value `x` in this scope\n";
+        assert_eq!(status(error, Exit::Code(1)), IceStatus::NotAnIce);
+        assert_eq!(status("", Exit::Timeout), IceStatus::Hang);
+        // Even if it ICEd before timing out
+        assert_eq!(status(RETIRED, Exit::Timeout), IceStatus::Hang);
+        // Killed, but not necessarily for lack of memory
+        assert_eq!(status("", Exit::Signal(9)), IceStatus::NotAnIce);
+        let limited = output("", Exit::Signal(9), Some(1 << 30));
+        assert_eq!(
+            analyze_output_with(&limited, &retired),
+            IceStatus::OutOfMemory
+        );
+        let alloc = "memory allocation of 1073741824 bytes failed\n";
+        assert_eq!(status(alloc, Exit::Signal(6)), IceStatus::OutOfMemory);
+        let llvm = "LLVM ERROR: out of memory\nAllocation failed\n";
+        assert_eq!(status(llvm, Exit::Code(1)), IceStatus::OutOfMemory);
+        // Other signals are crashes
+        assert_eq!(status("", Exit::Signal(11)), IceStatus::NotAnIce);
+        assert_eq!(
+            status(RETIRED, Exit::Code(101)),
+            IceStatus::MatchesFixed("ice/attic/1.rs")
+        );
+    }
+
+    #[test]
+    fn test_fixed() {
+        let retired = [known_ice("ice/attic/1.rs", RETIRED)];
+        let again = RETIRED.replace("`foo`", "`bar`");
+        assert_eq!(fixed(&again, &retired), Some("ice/attic/1.rs"));
+        let other = RETIRED.replace("retired.rs:12:34", "other.rs:12:34");
+        assert_eq!(fixed(&other, &retired), None);
+        assert_eq!(fixed(&again, &[]), None);
+
+        assert_eq!(
+            analyze_with(&again, &retired),
+            IceStatus::MatchesFixed("ice/attic/1.rs")
+        );
+        assert!(matches!(
+            analyze_with(&other, &retired),
+            IceStatus::AppearsNew(_)
+        ));
+    }
 }
@@ -1,5 +1,7 @@
 use std::path::PathBuf;
 
+use crate::dedup;
+
 #[derive(clap::Parser)]
 #[command(name = "drice")]
 #[command(about = "Dr. Ice diagnoses internal compiler errors (ICEs) in rustc")]
@@ -12,35 +14,175 @@ pub(crate) struct Cli {
     )]
     pub(crate) verbose: u8,
 
+    /// Database of known ICEs to load, a directory like ice/ or a file listing
+    /// programs in one (repeatable, later ones take precedence over earlier ones
+    /// and the built-in database)
+    #[arg(long, global = true, value_name = "PATH")]
+    pub(crate) db: Vec<PathBuf>,
+
+    #[command(flatten)]
+    pub(crate) rustc: RustcArgs,
+
     #[command(subcommand)]
     pub command: Command,
 }
 
+#[derive(clap::Args)]
+pub(crate) struct RustcArgs {
+    /// Rustup toolchain to run, e.g., `nightly-2025-06-01` or `stage1`
+    #[arg(
+        long,
+        global = true,
+        env = "DRICE_TOOLCHAIN",
+        default_value = "nightly"
+    )]
+    pub(crate) toolchain: String,
+
+    /// Path to a rustc binary to run (takes precedence over --toolchain)
+    #[arg(long, global = true, env = "DRICE_RUSTC")]
+    pub(crate) rustc: Option<PathBuf>,
+
+    /// Kill rustc after this many seconds (0 for no limit)
+    #[arg(long, global = true, value_name = "SECS", default_value_t = 60)]
+    pub(crate) timeout: u64,
+
+    /// Limit the address space of rustc to this many MiB
+    #[arg(long, global = true, value_name = "MIB")]
+    pub(crate) memory_limit: Option<u64>,
+
+    /// Always run rustc, ignoring cached output
+    #[arg(long, global = true)]
+    pub(crate) no_cache: bool,
+
+    /// Directory for cached rustc output (default: ~/.cache/drice)
+    #[arg(long, global = true, env = "DRICE_CACHE_DIR")]
+    pub(crate) cache_dir: Option<PathBuf>,
+}
+
 #[derive(clap::Subcommand)]
 pub(crate) enum Command {
-    /// Check if a program is a known ICE
+    /// Check if programs are known ICEs
     Check {
-        /// Path to the Rust source file to check
-        file: PathBuf,
+        /// Paths to Rust source files, directories with reproducers, rustc output, or
+        /// rustc-ice-*.txt files
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+
+        /// Number of rustc processes to run in parallel (default: number of CPUs)
+        #[arg(long, short = 'j')]
+        jobs: Option<usize>,
+
+        /// List the N known ICEs most similar to each ICE in the output, with
+        /// scores
+        #[arg(long, value_name = "N", default_value_t = 0)]
+        top: usize,
+
+        /// Explain, criterion by criterion, how each ICE compares to the known ICE
+        /// it's a duplicate of (or else the most similar one)
+        #[arg(long)]
+        explain: bool,
+
+        /// Known ICE to explain against, like 123 or ice/123.rs
+        #[arg(long, value_name = "ICE", requires = "explain")]
+        against: Option<String>,
     },
     /// Extract a MCVE from a GitHub issue
     Extract {
         /// Issue number from rust-lang/rust or path to local file
         issue_or_path: String,
     },
-    /// Run nightly rustc to see if a program ICEs
+    /// Run rustc to see if a program ICEs
     Rustc {
-        /// Rust source file (.rs)
+        /// Rust source file (.rs) or directory with a reproducer
         path: PathBuf,
     },
     /// Deduplicate ICEs in a directory
     Dedup {
-        /// Directory containing Rust source files to deduplicate
+        /// Directory containing Rust source files (or directories with reproducers) to
+        /// deduplicate
         directory: PathBuf,
+
+        /// Number of rustc processes to run in parallel (default: number of CPUs)
+        #[arg(long, short = 'j')]
+        jobs: Option<usize>,
+
+        /// Whether to move, copy or symlink programs into the output directories
+        #[arg(long, value_enum, default_value_t)]
+        mode: dedup::Mode,
+
+        /// Print where programs would go without touching the directory
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Regenerate the outputs of the known ICEs in ice/
+    Rebuild {
+        /// Number of rustc processes to run in parallel (default: number of CPUs)
+        #[arg(long, short = 'j')]
+        jobs: Option<usize>,
+
+        /// Move entries that no longer ICE to ice/attic/
+        #[arg(long)]
+        retire: bool,
+    },
+    /// Manage the database of known ICEs in ice/
+    Db {
+        /// Print what would be done without doing it
+        #[arg(long, global = true)]
+        dry_run: bool,
+
+        #[command(subcommand)]
+        command: DbCommand,
+    },
+    /// Manage the cache of rustc output
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommand,
     },
     /// Print file path, message, and query stack from a text or Rust file
     Glance {
-        /// Path to a text file (stderr output) or Rust source file (.rs)
+        /// Path to a text file (stderr output or rustc-ice-*.txt), Rust source file (.rs), or
+        /// directory with a reproducer
         file: PathBuf,
     },
 }
+
+#[derive(clap::Subcommand)]
+pub(crate) enum CacheCommand {
+    /// Delete all cached output
+    Clear,
+    /// Print the cache directory
+    Dir,
+}
+
+#[derive(clap::Subcommand)]
+pub(crate) enum DbCommand {
+    /// Add programs that ICE to ice/, or restore them from ice/attic/
+    Add {
+        /// Rust source files named after their issue, e.g., `NNN.rs`
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+
+        /// Add as duplicates of the entries with the same name
+        #[arg(long)]
+        dup: bool,
+    },
+    /// Move entries and their duplicates to ice/attic/
+    Retire {
+        /// Names of entries, e.g., `NNN` or `ice/NNN.rs`
+        #[arg(required = true)]
+        names: Vec<String>,
+    },
+    /// Derive the `//@ known-bug` headers of entries from their metadata
+    Normalize {
+        /// Names of entries, e.g., `NNN` or `ice/NNN.rs` (default: all)
+        names: Vec<String>,
+    },
+    /// Rerun the programs in ice/attic/ and report those that ICE again
+    Regressions {
+        /// Number of rustc processes to run in parallel (default: number of CPUs)
+        #[arg(long, short = 'j')]
+        jobs: Option<usize>,
+    },
+    /// Regenerate the index of entries embedded into drice
+    Reindex,
+}
@@ -0,0 +1,404 @@
+//! The known ICEs: those in `ice/`, embedded at build time (see
+//! [`crate::ice`]), layered under databases loaded at runtime with `--db`.
+//!
+//! A database is a directory like `ice/`, with programs `NNN.rs`, their
+//! outputs `NNN.out` and optional metadata `NNN.toml`, or an index file that
+//! lists programs, one per line, relative to the index. Outputs and metadata
+//! are next to the programs. Alternate reproducers of `NNN.rs` are in `dup/`
+//! next to it, as `NNN.rs`, `NNN.2.rs`, etc.
+//!
+//! Entries that no longer ICE are retired to `attic/` in a directory. Their
+//! outputs, if any, are their last ICEs, see [`retired`].
+
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Component, Path, PathBuf},
+    sync::OnceLock,
+};
+
+use anyhow::Context;
+use tracing::{debug, warn};
+
+use crate::ice::{DUPS, ICES, META, RETIRED};
+
+/// A known ICE
+#[derive(Debug, Clone)]
+pub(crate) struct Entry {
+    /// Path of the program, like `ice/123.rs`
+    pub(crate) name: &'static str,
+    /// Output of `rustc` on the program
+    pub(crate) stderr: &'static str,
+    /// Contents of `NNN.toml`, see [`crate::meta`]
+    pub(crate) meta: Option<&'static str>,
+    /// The alternate reproducers
+    pub(crate) dups: Vec<Dup>,
+}
+
+/// An alternate reproducer of a known ICE, in `dup/` next to it
+#[derive(Debug, Clone)]
+pub(crate) struct Dup {
+    /// Path of the program, like `ice/dup/123.2.rs`
+    pub(crate) name: &'static str,
+    /// Output of `rustc` on the program
+    pub(crate) stderr: &'static str,
+    /// Contents of `NNN.N.toml`, e.g., its provenance
+    pub(crate) meta: Option<&'static str>,
+}
+
+/// Name of the entry that the alternate reproducer `name` is of, like `123`
+/// for `123` and `123.2`
+#[must_use]
+pub(crate) fn dup_original(name: &str) -> &str {
+    name.split_once('.').map_or(name, |(original, _)| original)
+}
+
+/// Path of the entry that the alternate reproducer `dup` is of, like
+/// `ice/123.rs` for `ice/dup/123.2.rs`
+#[must_use]
+pub(crate) fn dup_entry(dup: &str) -> PathBuf {
+    let dup = Path::new(dup);
+    let name = dup.file_stem().and_then(|s| s.to_str()).unwrap_or_default();
+    dup.parent()
+        .and_then(Path::parent)
+        .unwrap_or(Path::new(""))
+        .join(format!("{}.rs", dup_original(name)))
+}
+
+/// Entries of the databases loaded with [`load`], the last one first
+static LOADED: OnceLock<Vec<Entry>> = OnceLock::new();
+
+/// Entries in `attic/` of the databases loaded with [`load`], the last one
+/// first
+static LOADED_RETIRED: OnceLock<Vec<Entry>> = OnceLock::new();
+
+/// `attic/` of the directories loaded with [`load`], the last one first
+static LOADED_ATTICS: OnceLock<Vec<PathBuf>> = OnceLock::new();
+
+fn read(path: &Path) -> anyhow::Result<&'static str> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("failed to read file: {}", path.display()))?;
+    // Loaded once, and kept until exit
+    Ok(content.leak())
+}
+
+/// The contents of `NNN.toml` next to the program `rs`, if any
+fn read_meta(rs: &Path) -> anyhow::Result<Option<&'static str>> {
+    let toml = rs.with_extension("toml");
+    if toml.exists() {
+        read(&toml).map(Some)
+    } else {
+        Ok(None)
+    }
+}
+
+/// The alternate reproducers of the program `rs` that have an output, in
+/// `dup/` next to it
+fn load_dups(rs: &Path) -> anyhow::Result<Vec<Dup>> {
+    let dir = rs.with_file_name("dup");
+    let Some(name) = rs.file_stem().and_then(|s| s.to_str()) else {
+        return Ok(Vec::new());
+    };
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut outs = Vec::new();
+    for entry in fs::read_dir(&dir)
+        .with_context(|| format!("failed to read directory: {}", dir.display()))?
+    {
+        let entry = entry
+            .with_context(|| format!("failed to read entry in directory: {}", dir.display()))?;
+        let path = entry.path();
+        let is_dup = path
+            .file_name()
+            .and_then(|s| s.to_str())
+            .is_some_and(|file| {
+                file.strip_suffix(".out")
+                    .is_some_and(|stem| dup_original(stem) == name)
+            });
+        if is_dup {
+            outs.push(path);
+        }
+    }
+    // `NNN` first, then `NNN.2`, ..., `NNN.10`
+    outs.sort_by_key(|out| (out.as_os_str().len(), out.clone()));
+    outs.iter()
+        .map(|out| {
+            let rs = out.with_extension("rs");
+            Ok(Dup {
+                stderr: read(out)?,
+                meta: read_meta(&rs)?,
+                name: rs.to_string_lossy().into_owned().leak(),
+            })
+        })
+        .collect()
+}
+
+/// The entry for the program `rs`, with its output, metadata and alternate
+/// reproducers
+fn load_entry(rs: &Path) -> anyhow::Result<Entry> {
+    Ok(Entry {
+        name: rs.to_string_lossy().into_owned().leak(),
+        stderr: read(&rs.with_extension("out"))?,
+        meta: read_meta(rs)?,
+        dups: load_dups(rs)?,
+    })
+}
+
+/// The programs in the directory `dir`, in lexical order
+fn programs(dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut programs = Vec::new();
+    for entry in
+        fs::read_dir(dir).with_context(|| format!("failed to read directory: {}", dir.display()))?
+    {
+        let entry = entry
+            .with_context(|| format!("failed to read entry in directory: {}", dir.display()))?;
+        let path = entry.path();
+        if path.is_file() && path.extension().and_then(|s| s.to_str()) == Some("rs") {
+            programs.push(path);
+        }
+    }
+    programs.sort();
+    Ok(programs)
+}
+
+/// The entries in the directory `dir`, in lexical order
+fn load_dir(dir: &Path) -> anyhow::Result<Vec<Entry>> {
+    let programs = programs(dir)?;
+    let mut entries = Vec::with_capacity(programs.len());
+    for rs in programs {
+        if rs.with_extension("out").exists() {
+            entries.push(load_entry(&rs)?);
+        } else {
+            warn!("{}: no output, skipping", rs.display());
+        }
+    }
+    Ok(entries)
+}
+
+/// The retired entries in `attic/` of the directory `dir` that have an output
+fn load_attic(dir: &Path) -> anyhow::Result<Vec<Entry>> {
+    let attic = dir.join("attic");
+    if !attic.is_dir() {
+        return Ok(Vec::new());
+    }
+    programs(&attic)?
+        .iter()
+        .filter(|rs| rs.with_extension("out").exists())
+        .map(|rs| load_entry(rs))
+        .collect()
+}
+
+/// The entries listed in the index file `index`
+fn load_index(index: &Path) -> anyhow::Result<Vec<Entry>> {
+    let dir = index.parent().unwrap_or(Path::new(""));
+    read(index)?
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            load_entry(&dir.join(line))
+                .with_context(|| format!("failed to load entry of {}: {line}", index.display()))
+        })
+        .collect()
+}
+
+/// Load the databases at `paths` (directories or index files), in order of
+/// precedence, lowest first. Has to happen before the entries are first used.
+pub(crate) fn load(paths: &[PathBuf]) -> anyhow::Result<()> {
+    let mut entries = Vec::new();
+    let mut retired = Vec::new();
+    let mut attics = Vec::new();
+    for path in paths.iter().rev() {
+        let layer = if path.is_dir() {
+            load_dir(path)
+        } else {
+            load_index(path)
+        }
+        .with_context(|| format!("failed to load database: {}", path.display()))?;
+        if layer.is_empty() {
+            anyhow::bail!("no known ICEs in database: {}", path.display());
+        }
+        debug!("{}: {} known ICEs", path.display(), layer.len());
+        entries.extend(layer);
+        if path.is_dir() {
+            let attic = load_attic(path)
+                .with_context(|| format!("failed to load database: {}", path.display()))?;
+            debug!("{}: {} retired ICEs", path.display(), attic.len());
+            retired.extend(attic);
+            attics.push(path.join("attic"));
+        }
+    }
+    LOADED_RETIRED
+        .set(retired)
+        .map_err(|_| anyhow::anyhow!("databases were already loaded"))?;
+    LOADED_ATTICS
+        .set(attics)
+        .map_err(|_| anyhow::anyhow!("databases were already loaded"))?;
+    LOADED
+        .set(entries)
+        .map_err(|_| anyhow::anyhow!("databases were already loaded"))
+}
+
+/// The embedded metadata of the entry or alternate reproducer `name`
+fn builtin_meta(name: &str) -> Option<&'static str> {
+    META.iter()
+        .find(|(nm, _)| *nm == name)
+        .map(|&(_, text)| text)
+}
+
+/// The embedded entries of `table`, like [`ICES`]
+fn builtin(table: &'static [(&'static str, &'static str)]) -> impl Iterator<Item = Entry> {
+    table.iter().map(|&(name, stderr)| Entry {
+        name,
+        stderr,
+        meta: builtin_meta(name),
+        dups: DUPS
+            .iter()
+            .filter(|(dup, _)| dup_entry(dup) == Path::new(name))
+            .map(|&(dup, stderr)| Dup {
+                name: dup,
+                stderr,
+                meta: builtin_meta(dup),
+            })
+            .collect(),
+    })
+}
+
+/// What `path` refers to, to tell whether two paths are the same program: its
+/// canonical path, or else `path` without `.` components
+pub(crate) fn identity(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| {
+        path.components()
+            .filter(|c| *c != Component::CurDir)
+            .collect()
+    })
+}
+
+/// Path of the embedded entry `name` (like `ice/123.rs`) in the source tree
+/// that `drice` was built from
+fn builtin_path(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join(name)
+}
+
+/// The `loaded` entries, followed by the embedded ones of `table` that they
+/// don't shadow: an entry shadows the entries of lower databases for the same
+/// program (see [`identity`]), e.g., `--db ./ice` shadows the embedded entries.
+fn layered(
+    loaded: &OnceLock<Vec<Entry>>,
+    table: &'static [(&'static str, &'static str)],
+) -> Vec<Entry> {
+    let loaded = loaded.get().map_or(&[][..], Vec::as_slice);
+    let mut seen = HashSet::new();
+    loaded
+        .iter()
+        .cloned()
+        .map(|entry| (identity(Path::new(entry.name)), entry))
+        .chain(builtin(table).map(|entry| (identity(&builtin_path(entry.name)), entry)))
+        .filter(|(id, _)| seen.insert(id.clone()))
+        .map(|(_, entry)| entry)
+        .collect()
+}
+
+/// All known ICEs, those of the databases loaded with [`load`] first
+pub(crate) fn entries() -> Vec<Entry> {
+    layered(&LOADED, ICES)
+}
+
+/// Retired ICEs: those in `ice/attic/` with an output (which `drice rebuild`
+/// keeps, and `drice db regressions` updates), under those in `attic/` of
+/// the databases loaded with [`load`]
+pub(crate) fn retired() -> Vec<Entry> {
+    layered(&LOADED_RETIRED, RETIRED)
+}
+
+/// The directories of retired entries: `attic/` of the databases loaded with
+/// [`load`], and `ice/attic`, relative to the source tree like the embedded
+/// entries
+pub(crate) fn attics() -> Vec<PathBuf> {
+    let loaded = LOADED_ATTICS.get().map_or(&[][..], Vec::as_slice);
+    let mut seen = HashSet::new();
+    loaded
+        .iter()
+        .cloned()
+        .chain([PathBuf::from("ice/attic")])
+        .filter(|attic| attic.exists() && seen.insert(identity(attic)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use std::path::Path;
+
+    use super::{builtin_path, dup_entry, identity, load_attic, load_dir, load_index};
+
+    #[test]
+    fn test_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |file: &str| dir.path().join(file);
+        fs::write(path("1.rs"), "fn main() {}").unwrap();
+        fs::write(path("1.out"), "error: internal compiler error: oops").unwrap();
+        fs::write(path("1.toml"), "[match]\nignore-query-stack = true").unwrap();
+        // Without an output
+        fs::write(path("2.rs"), "fn main() {}").unwrap();
+        fs::create_dir(path("dup")).unwrap();
+        fs::write(path("dup/1.out"), "dup").unwrap();
+        fs::write(path("dup/1.2.out"), "dup 2").unwrap();
+        fs::write(path("dup/1.2.toml"), "provenance = \"fuzzer\"").unwrap();
+        fs::write(path("dup/12.out"), "not a dup").unwrap();
+
+        let entries = load_dir(dir.path()).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].name, path("1.rs").to_string_lossy());
+        assert_eq!(entries[0].stderr, "error: internal compiler error: oops");
+        assert!(entries[0].meta.is_some());
+        let dups: Vec<_> = entries[0].dups.iter().map(|dup| dup.stderr).collect();
+        assert_eq!(dups, ["dup", "dup 2"]);
+        assert_eq!(
+            entries[0].dups[1].name,
+            path("dup/1.2.rs").to_string_lossy()
+        );
+        assert_eq!(entries[0].dups[0].meta, None);
+        assert_eq!(entries[0].dups[1].meta, Some("provenance = \"fuzzer\""));
+        assert!(load_attic(dir.path()).unwrap().is_empty());
+        fs::create_dir(path("attic")).unwrap();
+        fs::write(path("attic/3.rs"), "fn main() {}").unwrap();
+        fs::write(path("attic/3.out"), "error: internal compiler error: fixed").unwrap();
+        fs::write(path("attic/4.rs"), "fn main() {}").unwrap();
+        let retired = load_attic(dir.path()).unwrap();
+        assert_eq!(retired.len(), 1);
+        assert_eq!(retired[0].name, path("attic/3.rs").to_string_lossy());
+
+        fs::write(path("index"), "# Private ICEs\n1.rs\n").unwrap();
+        let entries = load_index(&path("index")).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].name, path("1.rs").to_string_lossy());
+        fs::write(path("index"), "2.rs\n").unwrap();
+        assert!(load_index(&path("index")).is_err());
+    }
+
+    #[test]
+    fn test_dup_entry() {
+        assert_eq!(dup_entry("ice/dup/123.rs"), Path::new("ice/123.rs"));
+        assert_eq!(
+            dup_entry("ice/attic/dup/123.2.rs"),
+            Path::new("ice/attic/123.rs")
+        );
+    }
+
+    #[test]
+    fn test_identity() {
+        assert_eq!(
+            identity(Path::new("./x/1.rs")),
+            identity(Path::new("x/1.rs"))
+        );
+        assert_ne!(identity(Path::new("x/1.rs")), identity(Path::new("y/1.rs")));
+        // Tests run in the source tree
+        assert_eq!(
+            identity(&builtin_path("ice/100041.rs")),
+            identity(Path::new("./ice/100041.rs"))
+        );
+    }
+}
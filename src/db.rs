@@ -0,0 +1,630 @@
+//! Maintenance of the database of known ICEs in `ice/`.
+//!
+//! Paths are relative to the root of the repository, like in [`crate::ice`].
+
+use std::{
+    fmt::Write as _,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use tracing::{info, warn};
+
+use crate::{
+    check,
+    database::{self, dup_original},
+    meta::{self, Meta},
+    par,
+    report::IceReport,
+    rustc,
+};
+
+pub(crate) const ICE_DIR: &str = "ice";
+pub(crate) const DUP_DIR: &str = "ice/dup";
+pub(crate) const ATTIC_DIR: &str = "ice/attic";
+pub(crate) const INDEX: &str = "src/ice.rs";
+
+/// Extensions of the files that make up an entry
+const EXTENSIONS: &[&str] = &["rs", "out", "run.json", "toml"];
+
+/// Names (file stems) of the `.rs` files in `dir`, sorted by file name
+pub(crate) fn names(dir: &Path) -> anyhow::Result<Vec<String>> {
+    let mut paths = Vec::new();
+    for entry in
+        fs::read_dir(dir).with_context(|| format!("failed to read directory: {}", dir.display()))?
+    {
+        let entry = entry
+            .with_context(|| format!("failed to read entry in directory: {}", dir.display()))?;
+        let path = entry.path();
+        if path.is_file() && path.extension().and_then(|s| s.to_str()) == Some("rs") {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    Ok(paths
+        .iter()
+        .filter_map(|p| p.file_stem()?.to_str().map(str::to_owned))
+        .collect())
+}
+
+/// Name of the entry for `arg`, which is a name or a path like `ice/NNN.rs`
+pub(crate) fn entry_name(arg: &str) -> String {
+    let base = Path::new(arg)
+        .file_name()
+        .and_then(|s| s.to_str())
+        .unwrap_or(arg);
+    base.strip_suffix(".rs").unwrap_or(base).to_owned()
+}
+
+fn rs_path(dir: &str, name: &str) -> PathBuf {
+    Path::new(dir).join(format!("{name}.rs"))
+}
+
+/// Names of the duplicates of entry `name` in `dir`, like `NNN` and `NNN.2`
+pub(crate) fn dups_of(dir: &Path, name: &str) -> anyhow::Result<Vec<String>> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    Ok(names(dir)?
+        .into_iter()
+        .filter(|dup| dup_original(dup) == name)
+        .collect())
+}
+
+/// The first free name for a duplicate of entry `name` in `dir`, like
+/// [`DUP_DIR`]: `NNN`, then `NNN.2`, `NNN.3`, etc.
+pub(crate) fn free_dup_name(dir: &Path, name: &str) -> String {
+    let mut dup = name.to_owned();
+    let mut n = 1;
+    while dir.join(format!("{dup}.rs")).exists() {
+        n += 1;
+        dup = format!("{name}.{n}");
+    }
+    dup
+}
+
+/// Move (or with `dry_run`, print) `src` to `dest`
+fn rename(src: &Path, dest: &Path, dry_run: bool) -> anyhow::Result<()> {
+    if dry_run {
+        println!("Would move {} to {}", src.display(), dest.display());
+        return Ok(());
+    }
+    info!("moving {} to {}", src.display(), dest.display());
+    if let Some(dir) = dest.parent() {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("failed to create directory: {}", dir.display()))?;
+    }
+    fs::rename(src, dest)
+        .with_context(|| format!("failed to rename {} to {}", src.display(), dest.display()))
+}
+
+/// Move the files of entry `name` from `from` to `to`
+fn move_entry(name: &str, from: &Path, to: &Path, dry_run: bool) -> anyhow::Result<()> {
+    for ext in EXTENSIONS {
+        let src = from.join(format!("{name}.{ext}"));
+        if src.exists() {
+            rename(&src, &to.join(format!("{name}.{ext}")), dry_run)?;
+        }
+    }
+    Ok(())
+}
+
+/// Move the files of entry `name` and of its duplicates from `from` to `to`,
+/// the duplicates from and to `dup/` in them
+fn move_with_dups(name: &str, from: &Path, to: &Path, dry_run: bool) -> anyhow::Result<()> {
+    move_entry(name, from, to, dry_run)?;
+    let (from, to) = (from.join("dup"), to.join("dup"));
+    for dup in dups_of(&from, name)? {
+        move_entry(&dup, &from, &to, dry_run)?;
+    }
+    Ok(())
+}
+
+/// Save the output of rustc on `rs` next to it
+pub(crate) fn save_output(rs: &Path, output: &rustc::Output) -> anyhow::Result<()> {
+    let out = rs.with_extension("out");
+    fs::write(&out, &output.stderr)
+        .with_context(|| format!("failed to write OUT file: {}", out.display()))?;
+    output.invocation().write(&out)
+}
+
+/// `code` with `header` as its `//@ known-bug` header, replacing the existing
+/// one or else inserted as the first line
+fn with_known_bug(code: &str, header: &str) -> String {
+    let mut lines: Vec<&str> = code.lines().collect();
+    match lines
+        .iter()
+        .position(|line| line.trim_start().starts_with("//@ known-bug:"))
+    {
+        Some(idx) => lines[idx] = header,
+        None => lines.insert(0, header),
+    }
+    let mut out = lines.join("\n");
+    if code.ends_with('\n') || code.is_empty() {
+        out.push('\n');
+    }
+    out
+}
+
+/// The metadata of the entry `rs`, from the file next to it
+fn read_meta(rs: &Path) -> anyhow::Result<Meta> {
+    let toml = rs.with_extension("toml");
+    if !toml.exists() {
+        return Ok(Meta::default());
+    }
+    let text = fs::read_to_string(&toml)
+        .with_context(|| format!("failed to read file: {}", toml.display()))?;
+    Meta::parse(&toml.to_string_lossy(), &text)
+}
+
+/// Derive the `//@ known-bug` header of the entry `rs` from its metadata, see
+/// [`Meta::known_bug`], returning whether it changed (or would change)
+fn normalize_file(rs: &Path, dry_run: bool) -> anyhow::Result<bool> {
+    let path = rs.to_string_lossy();
+    let meta = read_meta(rs)?;
+    let Some(header) = meta.known_bug(&path) else {
+        warn!("{path}: no issue, leaving its header as is");
+        return Ok(false);
+    };
+    let code =
+        fs::read_to_string(rs).with_context(|| format!("failed to read file: {}", rs.display()))?;
+    let normalized = with_known_bug(&code, &header);
+    if normalized == code {
+        return Ok(false);
+    }
+    if dry_run {
+        println!("Would set `{header}` in {path}");
+        return Ok(true);
+    }
+    info!("setting `{header}` in {path}");
+    fs::write(rs, normalized).with_context(|| format!("failed to write file: {}", rs.display()))?;
+    Ok(true)
+}
+
+/// Derive the `//@ known-bug` headers of the entries `names` (all if empty)
+/// from their metadata
+pub(crate) fn normalize(names: &[String], dry_run: bool) -> anyhow::Result<()> {
+    let names = if names.is_empty() {
+        self::names(Path::new(ICE_DIR))?
+    } else {
+        names.iter().map(|name| entry_name(name)).collect()
+    };
+    for name in names {
+        let rs = rs_path(ICE_DIR, &name);
+        if !rs.exists() {
+            anyhow::bail!("no such entry: {}", rs.display());
+        }
+        normalize_file(&rs, dry_run)?;
+    }
+    Ok(())
+}
+
+/// Check that `output` of a duplicate is the same ICE as the entry `original`
+fn check_dup(original: &Path, output: &rustc::Output) -> anyhow::Result<()> {
+    let out = original.with_extension("out");
+    let known = fs::read_to_string(&out)
+        .with_context(|| format!("failed to read file: {}", out.display()))?;
+    let text = output.text();
+    let rules = &meta::get(&original.to_string_lossy()).rules;
+    let same = check::same_parsed(
+        &text,
+        &IceReport::parse_all(&text),
+        &known,
+        &IceReport::parse_all(&known),
+        rules,
+    );
+    if !same {
+        anyhow::bail!(
+            "not the same ICE as {}, see `drice check --explain`",
+            original.display()
+        );
+    }
+    Ok(())
+}
+
+/// Add `file` to the database, or as a duplicate of the entry of the same name
+/// (see [`free_dup_name`]) if it's the same ICE.
+///
+/// Files in [`ATTIC_DIR`] are restored along with their duplicates.
+/// Refuses files that don't ICE. Doesn't update the index, see [`reindex`].
+pub(crate) fn add(
+    config: &rustc::RustcConfig,
+    file: &Path,
+    dup: bool,
+    dry_run: bool,
+) -> anyhow::Result<()> {
+    let name = entry_name(&file.to_string_lossy());
+    let restoring = file
+        .parent()
+        .is_some_and(|dir| database::identity(dir) == database::identity(Path::new(ATTIC_DIR)));
+    let original = rs_path(ICE_DIR, dup_original(&name));
+    let dest = if restoring || !dup {
+        rs_path(ICE_DIR, &name)
+    } else {
+        if !original.exists() {
+            anyhow::bail!("no original for duplicate: {}", original.display());
+        }
+        rs_path(
+            DUP_DIR,
+            &free_dup_name(Path::new(DUP_DIR), dup_original(&name)),
+        )
+    };
+    if dest.exists() {
+        anyhow::bail!("entry already exists: {}", dest.display());
+    }
+
+    let output = rustc::go(config, file)
+        .with_context(|| format!("failed to run rustc on file: {}", file.display()))?;
+    if output.exit == rustc::Exit::Timeout || check::is_ice(&output.text()).is_none() {
+        anyhow::bail!("Not an ICE: {}", file.display());
+    }
+    if dup && !restoring {
+        check_dup(&original, &output)
+            .with_context(|| format!("not adding duplicate: {}", file.display()))?;
+    }
+    if restoring {
+        move_with_dups(&name, Path::new(ATTIC_DIR), Path::new(ICE_DIR), dry_run)?;
+    } else {
+        rename(file, &dest, dry_run)?;
+    }
+    if dry_run {
+        return Ok(());
+    }
+    if !dup && !restoring {
+        normalize_file(&dest, dry_run)?;
+    }
+    // The output has to mention the program where it is now, with its header
+    let output = rustc::go(&config.uncached(), &dest)
+        .with_context(|| format!("failed to run rustc on file: {}", dest.display()))?;
+    save_output(&dest, &output)
+}
+
+/// Move an entry and its duplicates to the attic.
+///
+/// Doesn't update the index, see [`reindex`].
+pub(crate) fn retire(name: &str, dry_run: bool) -> anyhow::Result<()> {
+    let rs = rs_path(ICE_DIR, name);
+    if !rs.exists() {
+        anyhow::bail!("no such entry: {}", rs.display());
+    }
+    move_with_dups(name, Path::new(ICE_DIR), Path::new(ATTIC_DIR), dry_run)
+}
+
+/// The programs of the retired entries in `attic`, and their duplicates
+fn retired_reproducers(attic: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for dir in [attic.to_owned(), attic.join("dup")] {
+        if dir.exists() {
+            for name in names(&dir)? {
+                files.push(dir.join(format!("{name}.rs")));
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// The program of the entry that `rs` is a reproducer of, `rs` itself unless
+/// it's in `dup/`
+fn original_of(rs: &Path) -> PathBuf {
+    match rs.parent() {
+        Some(dir) if dir.file_name().is_some_and(|name| name == "dup") => {
+            let name = entry_name(&rs.to_string_lossy());
+            dir.with_file_name(format!("{}.rs", dup_original(&name)))
+        }
+        _ => rs.to_owned(),
+    }
+}
+
+/// The programs in `files` that ICE when `run`, with their output. Programs
+/// that can't be run, e.g., as they lost an auxiliary crate, are skipped.
+fn ice_again(
+    files: &[PathBuf],
+    jobs: usize,
+    run: impl Fn(&Path) -> anyhow::Result<rustc::Output> + Sync,
+) -> anyhow::Result<Vec<(PathBuf, rustc::Output)>> {
+    let mut again = Vec::new();
+    par::for_each_ordered(
+        files,
+        jobs,
+        |rs| run(rs),
+        |rs, output| {
+            match output {
+                Ok(output)
+                    if output.exit != rustc::Exit::Timeout
+                        && check::is_ice(&output.text()).is_some() =>
+                {
+                    again.push((rs.clone(), output));
+                }
+                Ok(_) => {}
+                Err(e) => warn!("{e:#}"),
+            }
+            Ok(())
+        },
+    )?;
+    Ok(again)
+}
+
+/// Run rustc on the reproducers of the retired entries (see
+/// [`crate::database::attics`]) and report those that ICE again, saving their
+/// output (see [`crate::database::retired`]).
+pub(crate) fn regressions(
+    config: &rustc::RustcConfig,
+    jobs: usize,
+    dry_run: bool,
+) -> anyhow::Result<()> {
+    let mut files = Vec::new();
+    for attic in database::attics() {
+        files.extend(retired_reproducers(&attic)?);
+    }
+    // Outputs are saved, so they have to be of these files
+    let config = config.uncached();
+    let again = ice_again(&files, jobs, |rs| {
+        rustc::go(&config, rs)
+            .with_context(|| format!("failed to run rustc on file: {}", rs.display()))
+    })?;
+    for (rs, output) in &again {
+        let original = original_of(rs);
+        match read_meta(&original)?.issue(&original.to_string_lossy()) {
+            Some(issue) => println!("ICEs again: {} (#{issue})", rs.display()),
+            None => println!("ICEs again: {}", rs.display()),
+        }
+        if !dry_run {
+            save_output(rs, output)?;
+        }
+    }
+    println!(
+        "{} of {} retired reproducers ICE again",
+        again.len(),
+        files.len()
+    );
+    Ok(())
+}
+
+/// A line of [`INDEX`] for `file` of entry `name`
+fn index_entry(out: &mut String, name: &str, file: &str) {
+    let rs = format!("\"{ICE_DIR}/{name}.rs\"");
+    let include = format!("include_str!(\"../{ICE_DIR}/{file}\")");
+    let tuple = format!("{rs}, {include}");
+    // Match rustfmt, see `fn_call_width`
+    if tuple.len() <= 60 {
+        let _ = writeln!(out, "    ({tuple}),");
+    } else {
+        let _ = write!(out, "    (\n        {rs},\n        {include},\n    ),\n");
+    }
+}
+
+/// What [`INDEX`] lists, by names relative to [`ICE_DIR`], like `123` or
+/// `attic/123`
+struct Contents {
+    names: Vec<String>,
+    /// Entries in [`ATTIC_DIR`] with an output, see
+    /// [`crate::database::retired`]
+    retired: Vec<String>,
+    /// Duplicates of [`Self::names`] and [`Self::retired`], like `dup/123.2`
+    /// or `attic/dup/123`
+    dups: Vec<String>,
+    /// Entries and duplicates with metadata (see [`crate::meta`])
+    with_meta: Vec<String>,
+}
+
+impl Contents {
+    fn read() -> anyhow::Result<Self> {
+        let ice = Path::new(ICE_DIR);
+        let names = names(ice)?;
+        let attic = Path::new(ATTIC_DIR);
+        let retired: Vec<_> = if attic.exists() {
+            self::names(attic)?
+                .into_iter()
+                .map(|name| format!("attic/{name}"))
+                .filter(|name| ice.join(format!("{name}.out")).exists())
+                .collect()
+        } else {
+            Vec::new()
+        };
+        let mut dups: Vec<_> = self::names(Path::new(DUP_DIR))?
+            .into_iter()
+            .map(|dup| format!("dup/{dup}"))
+            .collect();
+        let attic_dups = attic.join("dup");
+        if attic_dups.exists() {
+            dups.extend(
+                self::names(&attic_dups)?
+                    .into_iter()
+                    .filter(|dup| {
+                        retired.contains(&format!("attic/{}", dup_original(dup)))
+                            && attic_dups.join(format!("{dup}.out")).exists()
+                    })
+                    .map(|dup| format!("attic/dup/{dup}")),
+            );
+        }
+        let with_meta = names
+            .iter()
+            .chain(&retired)
+            .chain(&dups)
+            .filter(|name| ice.join(format!("{name}.toml")).exists())
+            .cloned()
+            .collect();
+        Ok(Self {
+            names,
+            retired,
+            dups,
+            with_meta,
+        })
+    }
+}
+
+/// A constant of [`INDEX`] named `name`, of entries and their files, only
+/// filled in with `embed` if `outputs`
+fn table(out: &mut String, name: &str, outputs: bool, files: &[(&str, String)]) {
+    let ty = "&[(&str, &str)]";
+    if outputs {
+        let _ = writeln!(
+            out,
+            "#[cfg(not(feature = \"embed\"))]\npub(crate) const {name}: {ty} = &[];"
+        );
+        out.push_str("#[cfg(feature = \"embed\")]\n");
+    } else if files.is_empty() {
+        let _ = writeln!(out, "pub(crate) const {name}: {ty} = &[];");
+        return;
+    }
+    let _ = writeln!(out, "pub(crate) const {name}: {ty} = &[");
+    for (entry, file) in files {
+        index_entry(out, entry, file);
+    }
+    out.push_str("];\n");
+}
+
+/// The files with extension `ext` of the entries `names`
+fn files<'a>(names: &'a [String], ext: &str) -> Vec<(&'a str, String)> {
+    names
+        .iter()
+        .map(|name| (name.as_str(), format!("{name}.{ext}")))
+        .collect()
+}
+
+/// Generate the source of [`INDEX`]
+fn index(contents: &Contents) -> String {
+    let mut out = String::from("// DO NOT EDIT: generated by `drice db reindex`\n");
+    table(&mut out, "ICES", true, &files(&contents.names, "out"));
+    table(&mut out, "RETIRED", true, &files(&contents.retired, "out"));
+    table(&mut out, "DUPS", true, &files(&contents.dups, "out"));
+    table(&mut out, "META", false, &files(&contents.with_meta, "toml"));
+    out
+}
+
+/// Regenerate [`INDEX`] from the contents of [`ICE_DIR`]
+pub(crate) fn reindex(dry_run: bool) -> anyhow::Result<()> {
+    let contents = Contents::read()?;
+    let count = contents.names.len();
+    let path = PathBuf::from(INDEX);
+    let content = index(&contents);
+    if fs::read_to_string(&path).is_ok_and(|old| old == content) {
+        info!("{} is up to date", path.display());
+        return Ok(());
+    }
+    if dry_run {
+        println!("Would write {count} entries to {}", path.display());
+        return Ok(());
+    }
+    fs::write(&path, content)
+        .with_context(|| format!("failed to write file: {}", path.display()))?;
+    info!("wrote {count} entries to {}", path.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use super::{
+        Contents, DUP_DIR, ICE_DIR, INDEX, check_dup, dup_original, entry_name, free_dup_name,
+        ice_again, index, names, normalize_file, original_of, retired_reproducers, rs_path,
+        with_known_bug,
+    };
+    use crate::{meta::Meta, rustc};
+
+    #[test]
+    fn test_index_up_to_date() {
+        let expected = index(&Contents::read().unwrap());
+        let actual = fs::read_to_string(INDEX).unwrap();
+        assert!(
+            expected == actual,
+            "{INDEX} is out of date, run `drice db reindex`"
+        );
+    }
+
+    #[test]
+    fn test_dups_have_originals() {
+        for name in names(Path::new(DUP_DIR)).unwrap() {
+            let original = Path::new(ICE_DIR).join(format!("{}.rs", dup_original(&name)));
+            assert!(original.exists(), "{DUP_DIR}/{name}.rs has no original");
+            let toml = Path::new(DUP_DIR).join(format!("{name}.toml"));
+            if let Ok(text) = fs::read_to_string(&toml) {
+                Meta::parse(&toml.to_string_lossy(), &text).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn test_free_dup_name() {
+        let dup = names(Path::new(DUP_DIR)).unwrap().remove(0);
+        let name = dup_original(&dup);
+        let free = free_dup_name(Path::new(DUP_DIR), name);
+        assert!(free.starts_with(&format!("{name}.")), "{free}");
+        assert!(!rs_path(DUP_DIR, &free).exists(), "{free}");
+        assert_eq!(free_dup_name(Path::new(DUP_DIR), "0"), "0");
+    }
+
+    #[test]
+    fn test_entry_name() {
+        assert_eq!(entry_name("123"), "123");
+        assert_eq!(entry_name("123-2.rs"), "123-2");
+        assert_eq!(entry_name("ice/attic/123.rs"), "123");
+    }
+
+    #[test]
+    fn test_with_known_bug() {
+        let header = "//@ known-bug: #123";
+        assert_eq!(
+            with_known_bug("//@ known-bug: rust-lang/rust#123\nfn main() {}\n", header),
+            "//@ known-bug: #123\nfn main() {}\n"
+        );
+        assert_eq!(
+            with_known_bug("//@ edition: 2021\nfn main() {}", header),
+            "//@ known-bug: #123\n//@ edition: 2021\nfn main() {}"
+        );
+    }
+
+    #[test]
+    fn test_known_bug_normalized() {
+        for name in names(Path::new(ICE_DIR)).unwrap() {
+            let rs = Path::new(ICE_DIR).join(format!("{name}.rs"));
+            assert!(
+                !normalize_file(&rs, true).unwrap(),
+                "The header of {} isn't normalized, run `drice db normalize`",
+                rs.display()
+            );
+        }
+    }
+
+    #[test]
+    fn test_regressions() {
+        let dir = tempfile::tempdir().unwrap();
+        let attic = dir.path().join("attic");
+        fs::create_dir_all(attic.join("dup")).unwrap();
+        for file in ["1.rs", "2.rs", "3.rs", "dup/1.2.rs"] {
+            fs::write(attic.join(file), "fn main() {}").unwrap();
+        }
+        let files = retired_reproducers(&attic).unwrap();
+        assert_eq!(
+            files,
+            ["1.rs", "2.rs", "3.rs", "dup/1.2.rs"].map(|file| attic.join(file))
+        );
+        assert_eq!(original_of(&files[3]), files[0]);
+        assert_eq!(original_of(&files[1]), files[1]);
+
+        let again = ice_again(&files, 2, |rs| match rs.file_name().unwrap().to_str() {
+            Some("1.rs" | "1.2.rs") => Ok(rustc::output(
+                "error: internal compiler error: compiler/rustc_middle/src/ty/mod.rs:1:1: oops\n",
+            )),
+            Some("2.rs") => Ok(rustc::output("error[E0308]: mismatched types\n")),
+            _ => anyhow::bail!("failed to read auxiliary crate"),
+        })
+        .unwrap();
+        let again: Vec<_> = again.into_iter().map(|(rs, _)| rs).collect();
+        assert_eq!(again, [files[0].clone(), files[3].clone()]);
+    }
+
+    #[test]
+    fn test_check_dup() {
+        let dir = tempfile::tempdir().unwrap();
+        let original = dir.path().join("1.rs");
+        let ice = "error: internal compiler error: compiler/rustc_middle/src/ty/mod.rs:1:1: oops\n";
+        assert!(check_dup(&original, &rustc::output(ice)).is_err());
+        fs::write(original.with_extension("out"), ice).unwrap();
+        check_dup(&original, &rustc::output(ice)).unwrap();
+        let other = ice.replace("ty/mod.rs", "mir/mod.rs");
+        assert!(check_dup(&original, &rustc::output(&other)).is_err());
+    }
+}
@@ -1,14 +1,240 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::ffi::OsStr;
+use std::fmt::Write as _;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use anyhow::Context;
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
 
 use crate::check;
+use crate::index::SignatureIndex;
+use crate::meta::MatchRules;
+use crate::par;
+use crate::project;
+use crate::report::{IceKind, IceReport};
 use crate::rustc;
 
 pub(crate) struct DedupConfig {
     pub directory: PathBuf,
+    pub rustc: rustc::RustcConfig,
+    pub jobs: usize,
+    pub mode: Mode,
+    /// Print what would be done without touching the directory
+    pub dry_run: bool,
+}
+
+/// Name of the report that [`dedup`] writes to the directory
+const REPORT: &str = "dedup.json";
+
+/// What an ICE looks like, for reviewing clusters
+#[derive(Debug, serde::Serialize)]
+struct Signature {
+    kind: IceKind,
+    location: Option<String>,
+    message: Option<String>,
+    /// See [`IceReport::signature`]
+    functions: Vec<String>,
+}
+
+impl Signature {
+    fn new(report: &IceReport) -> Self {
+        Self {
+            kind: report.kind,
+            location: report
+                .location
+                .as_ref()
+                .map(|l| format!("{}:{}:{}", l.source_path(), l.line, l.column)),
+            message: report.normalized_message(),
+            functions: report.signature(),
+        }
+    }
+}
+
+/// Programs that produce the same ICE(s), the first in lexical order being
+/// the representative, which stays in place
+#[derive(Debug, serde::Serialize)]
+struct Cluster {
+    representative: PathBuf,
+    /// The duplicates, in `dups/`
+    members: Vec<PathBuf>,
+    /// Each ICE of the representative
+    ices: Vec<Signature>,
+}
+
+/// A program that duplicates an entry of the database
+#[derive(Debug, serde::Serialize)]
+struct Known {
+    file: PathBuf,
+    /// Where it was moved, copied or linked, in `known/`
+    dest: PathBuf,
+    entry: &'static str,
+}
+
+/// The outcome of [`dedup`], written to [`REPORT`]
+#[derive(Debug, Default, serde::Serialize)]
+struct Report {
+    clusters: Vec<Cluster>,
+    known: Vec<Known>,
+    /// Programs that don't ICE, in `ok/`
+    ok: Vec<PathBuf>,
+    /// Programs that made `rustc` time out, in `hang/`
+    hang: Vec<PathBuf>,
+    /// Programs that made `rustc` run out of memory, in `oom/`
+    oom: Vec<PathBuf>,
+}
+
+impl Report {
+    /// A summary for humans, one line per cluster and known ICE
+    fn summary(&self) -> String {
+        let dups: usize = self.clusters.iter().map(|c| c.members.len()).sum();
+        let mut s = format!(
+            "{} unique ICE(s) with {dups} duplicate(s), {} known, {} not an ICE, {} hang(s), {} out of memory",
+            self.clusters.len(),
+            self.known.len(),
+            self.ok.len(),
+            self.hang.len(),
+            self.oom.len()
+        );
+        for cluster in &self.clusters {
+            let _ = write!(
+                s,
+                "\n{} ({} duplicate(s))",
+                cluster.representative.display(),
+                cluster.members.len()
+            );
+            if let Some(ice) = cluster.ices.first() {
+                let location = ice.location.as_deref().unwrap_or("(no location)");
+                let message = ice.message.as_deref().unwrap_or("");
+                let message = message.lines().next().unwrap_or_default();
+                let _ = write!(s, ": {location}: {message}");
+            }
+            for member in &cluster.members {
+                let _ = write!(s, "\n  {}", member.display());
+            }
+        }
+        for known in &self.known {
+            let _ = write!(s, "\n{}: known, {}", known.file.display(), known.entry);
+        }
+        s
+    }
+
+    fn write(&self, path: &Path) -> anyhow::Result<()> {
+        let content =
+            serde_json::to_string_pretty(self).context("failed to serialize dedup report")?;
+        fs::write(path, content + "\n")
+            .with_context(|| format!("failed to write file: {}", path.display()))
+    }
+}
+
+/// What to do with each program once it's sorted
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum Mode {
+    /// Move it
+    #[default]
+    Move,
+    /// Copy it, leaving the original in place
+    Copy,
+    /// Symlink to it, leaving the original in place
+    Symlink,
+}
+
+/// Copy a file, or a directory with its contents
+fn copy_all(src: &Path, dest: &Path) -> anyhow::Result<()> {
+    if !src.is_dir() {
+        fs::copy(src, dest)
+            .with_context(|| format!("failed to copy {} to {}", src.display(), dest.display()))?;
+        return Ok(());
+    }
+    fs::create_dir(dest)
+        .with_context(|| format!("failed to create directory: {}", dest.display()))?;
+    for entry in
+        fs::read_dir(src).with_context(|| format!("failed to read directory: {}", src.display()))?
+    {
+        let entry = entry
+            .with_context(|| format!("failed to read entry in directory: {}", src.display()))?;
+        copy_all(&entry.path(), &dest.join(entry.file_name()))?;
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn symlink(src: &Path, dest: &Path) -> anyhow::Result<()> {
+    let src = src
+        .canonicalize()
+        .with_context(|| format!("failed to resolve path: {}", src.display()))?;
+    std::os::unix::fs::symlink(&src, dest)
+        .with_context(|| format!("failed to symlink {} to {}", dest.display(), src.display()))
+}
+
+#[cfg(not(unix))]
+fn symlink(_src: &Path, _dest: &Path) -> anyhow::Result<()> {
+    anyhow::bail!("symlinks are not supported on this platform")
+}
+
+/// Puts programs into the output directories, without overwriting anything
+#[derive(Debug)]
+struct Placer {
+    mode: Mode,
+    dry_run: bool,
+    /// Destinations so far, which don't exist yet with `dry_run`
+    taken: HashSet<PathBuf>,
+}
+
+impl Placer {
+    /// `dir/name`, or `dir/name-N.ext` with the first `N` that is free
+    fn free_path(&self, dir: &Path, name: &OsStr) -> PathBuf {
+        let is_free =
+            |path: &Path| !self.taken.contains(path) && fs::symlink_metadata(path).is_err();
+        let path = dir.join(name);
+        if is_free(&path) {
+            return path;
+        }
+        let name = Path::new(name);
+        let stem = name.file_stem().unwrap_or_default().to_string_lossy();
+        let ext = name
+            .extension()
+            .map(|ext| format!(".{}", ext.to_string_lossy()))
+            .unwrap_or_default();
+        (1..)
+            .map(|n| dir.join(format!("{stem}-{n}{ext}")))
+            .find(|path| is_free(path))
+            .expect("there is a free path")
+    }
+
+    /// Put `file` into `dir`, under its own name or `name`, returning its new
+    /// path
+    fn place(&mut self, file: &Path, dir: &Path, name: Option<&OsStr>) -> anyhow::Result<PathBuf> {
+        let name = match name {
+            Some(name) => name,
+            None => file.file_name().with_context(|| {
+                format!("failed to get file name from path: {}", file.display())
+            })?,
+        };
+        let dest = self.free_path(dir, name);
+        self.taken.insert(dest.clone());
+        if self.dry_run {
+            let verb = match self.mode {
+                Mode::Move => "move",
+                Mode::Copy => "copy",
+                Mode::Symlink => "symlink",
+            };
+            println!("Would {verb} {} to {}", file.display(), dest.display());
+            return Ok(dest);
+        }
+        match self.mode {
+            Mode::Move => fs::rename(file, &dest).with_context(|| {
+                format!(
+                    "failed to rename file {} to {}",
+                    file.display(),
+                    dest.display()
+                )
+            })?,
+            Mode::Copy => copy_all(file, &dest)?,
+            Mode::Symlink => symlink(file, &dest)?,
+        }
+        Ok(dest)
+    }
 }
 
 pub(crate) fn dedup(config: DedupConfig) -> anyhow::Result<()> {
@@ -18,14 +244,22 @@ pub(crate) fn dedup(config: DedupConfig) -> anyhow::Result<()> {
     let dups_dir = dir.join("dups");
     let ok_dir = dir.join("ok");
     let known_dir = dir.join("known");
-    fs::create_dir_all(&dups_dir)
-        .with_context(|| format!("failed to create directory: {}", dups_dir.display()))?;
-    fs::create_dir_all(&ok_dir)
-        .with_context(|| format!("failed to create directory: {}", ok_dir.display()))?;
-    fs::create_dir_all(&known_dir)
-        .with_context(|| format!("failed to create directory: {}", known_dir.display()))?;
-
-    // Collect all .rs files in lexical order
+    let hang_dir = dir.join("hang");
+    let oom_dir = dir.join("oom");
+    if !config.dry_run {
+        for d in [&dups_dir, &ok_dir, &known_dir, &hang_dir, &oom_dir] {
+            fs::create_dir_all(d)
+                .with_context(|| format!("failed to create directory: {}", d.display()))?;
+        }
+    }
+    let mut placer = Placer {
+        mode: config.mode,
+        dry_run: config.dry_run,
+        taken: HashSet::new(),
+    };
+
+    // Collect all .rs files and multi-file reproducers in lexical order
+    let outputs = [&dups_dir, &ok_dir, &known_dir, &hang_dir, &oom_dir];
     let mut files: Vec<PathBuf> = Vec::new();
     for entry in
         fs::read_dir(dir).with_context(|| format!("failed to read directory: {}", dir.display()))?
@@ -33,39 +267,89 @@ pub(crate) fn dedup(config: DedupConfig) -> anyhow::Result<()> {
         let entry = entry
             .with_context(|| format!("failed to read entry in directory: {}", dir.display()))?;
         let path = entry.path();
-        if path.is_file() && path.extension().and_then(|s| s.to_str()) == Some("rs") {
-            files.push(path);
+        if !project::is_reproducer(&path) || outputs.contains(&&path) {
+            continue;
         }
+        if path.is_dir() && !project::is_project(&path) {
+            warn!(
+                "{}: skipping, not a Cargo project nor a directory with one .rs file",
+                path.display()
+            );
+            continue;
+        }
+        files.push(path);
     }
     files.sort();
 
     debug!("Processing {} programs", files.len());
 
-    // Track unique ICEs: map from ICE stderr to the first file that produced it
-    let mut unique_ices: Vec<(PathBuf, String)> = Vec::new();
+    // Track unique ICEs: the first file that produced each, with its output
+    let mut unique_ices: Vec<(PathBuf, String, Vec<IceReport>)> = Vec::new();
+    // ...and indexes of them, by output and by their ICEs
+    let mut by_stderr: HashMap<String, usize> = HashMap::new();
+    let mut index: SignatureIndex<usize> = SignatureIndex::default();
+    let mut report = Report::default();
 
     let bar = indicatif::ProgressBar::new(
         u64::try_from(files.len())
             .with_context(|| format!("files length {} exceeds u64::MAX", files.len()))?,
     );
-    for file in &files {
+
+    // Run rustc and look up known ICEs in parallel, but decide what is a
+    // duplicate in lexical order so that the result is deterministic.
+    let work = |file: &PathBuf| -> anyhow::Result<(rustc::Output, Option<&'static str>)> {
         debug!("Processing {}", file.display());
-        let stderr = rustc::go(file.as_path())
-            .with_context(|| format!("failed to run rustc on file: {}", file.display()))?;
+        let output = rustc::go(&config.rustc, file.as_path())
+            .with_context(|| format!("failed to run rustc on: {}", file.display()))?;
+        let known = if output.exit != rustc::Exit::Timeout
+            && !output.out_of_memory()
+            && check::is_ice(&output.text()).is_some()
+        {
+            check::exists(&output.text())
+        } else {
+            None
+        };
+        bar.inc(1);
+        Ok((output, known))
+    };
+    let sorted = par::for_each_ordered(&files, config.jobs, work, |file, result| {
+        let (output, known) = result?;
 
+        if output.exit == rustc::Exit::Timeout {
+            info!("{}: hang, sorting into hang/", file.display());
+            report.hang.push(placer.place(file, &hang_dir, None)?);
+            return Ok(());
+        }
+
+        if output.out_of_memory() {
+            info!("{}: out of memory, sorting into oom/", file.display());
+            report.oom.push(placer.place(file, &oom_dir, None)?);
+            return Ok(());
+        }
+
+        let stderr = output.text().into_owned();
         if check::is_ice(&stderr).is_none() {
-            let file_name = file.file_name().with_context(|| {
-                format!("failed to get file name from path: {}", file.display())
-            })?;
-            let dest = ok_dir.join(file_name);
-            info!("{}: not an ice, moving to ok/", file.display());
-            fs::rename(file, &dest)
-                .with_context(|| format!("failed to rename file {} to ok/", file.display()))?;
-            bar.inc(1);
-            continue;
+            info!("{}: not an ice, sorting into ok/", file.display());
+            report.ok.push(placer.place(file, &ok_dir, None)?);
+            return Ok(());
+        }
+
+        if let Some(known_ice_path) = known
+            && file.is_dir()
+        {
+            info!(
+                "{}: duplicate of {known_ice_path}, sorting into known/",
+                file.display()
+            );
+            report.known.push(Known {
+                file: file.clone(),
+                dest: placer.place(file, &known_dir, None)?,
+                entry: known_ice_path,
+            });
+            return Ok(());
         }
 
-        if let Some(known_ice_path) = check::exists(&stderr) {
+        if let Some(known_ice_path) = known {
             let known_file_name = PathBuf::from(known_ice_path)
                 .file_name()
                 .with_context(|| {
@@ -73,44 +357,174 @@ pub(crate) fn dedup(config: DedupConfig) -> anyhow::Result<()> {
                 })?
                 .to_owned();
 
+            // Named after the entry, as the names of the programs often
+            // don't say much, e.g. from a fuzzer
+            let dest = placer.place(file, &known_dir, Some(&known_file_name))?;
             info!(
-                "{}: duplicate of {}, moving to known/{}",
+                "{}: duplicate of {}, sorting into {}",
                 file.display(),
                 known_file_name.display(),
-                known_file_name.display(),
+                dest.display(),
             );
-            fs::rename(file, known_dir.join(known_file_name))
-                .with_context(|| format!("failed to rename file {} to known/", file.display()))?;
-            bar.inc(1);
-            continue;
+            report.known.push(Known {
+                file: file.clone(),
+                dest,
+                entry: known_ice_path,
+            });
+            return Ok(());
         }
 
-        let mut is_duplicate = false;
-        for (original_file, original_stderr) in &unique_ices {
-            if check::same(&stderr, original_stderr) {
-                // Found a duplicate
-                let file_name = file.file_name().with_context(|| {
-                    format!("failed to get file name from path: {}", file.display())
-                })?;
-                let dest = dups_dir.join(file_name);
+        let reports = IceReport::parse_all(&stderr);
+        // Every ICE has to match, so the first one narrows it down enough
+        let mut candidates: BTreeSet<usize> = reports
+            .first()
+            .map(|first| index.candidates(first))
+            .unwrap_or_default()
+            .into_iter()
+            .collect();
+        candidates.extend(by_stderr.get(&stderr));
+        for i in candidates {
+            let (original_file, original_stderr, original_reports) = &unique_ices[i];
+            if check::same_parsed(
+                &stderr,
+                &reports,
+                original_stderr,
+                original_reports,
+                &MatchRules::default(),
+            ) {
                 info!(
-                    "{}: duplicate of {}, moving to dups/",
+                    "{}: duplicate of {}, sorting into dups/",
                     file.display(),
                     original_file.display()
                 );
-                fs::rename(file, &dest).with_context(|| {
-                    format!("failed to rename file {} to dups/", file.display())
-                })?;
-                is_duplicate = true;
-                break;
+                report.clusters[i]
+                    .members
+                    .push(placer.place(file, &dups_dir, None)?);
+                return Ok(());
             }
         }
 
-        if !is_duplicate {
-            unique_ices.push((file.clone(), stderr));
+        report.clusters.push(Cluster {
+            representative: file.clone(),
+            members: Vec::new(),
+            ices: reports.iter().map(Signature::new).collect(),
+        });
+        let i = unique_ices.len();
+        for r in &reports {
+            index.insert(i, r, false);
         }
-        bar.inc(1);
+        by_stderr.entry(stderr.clone()).or_insert(i);
+        unique_ices.push((file.clone(), stderr, reports));
+        Ok(())
+    });
+    bar.finish();
+    if let Err(e) = sorted {
+        // Programs may have been moved already, so keep their clustering
+        if !config.dry_run {
+            let path = dir.join(REPORT);
+            match report.write(&path) {
+                Ok(()) => eprintln!("Partial report written to {}", path.display()),
+                Err(write) => warn!("{write:#}"),
+            }
+        }
+        return Err(e);
     }
 
+    println!("{}", report.summary());
+    if !config.dry_run {
+        let path = dir.join(REPORT);
+        report.write(&path)?;
+        println!("Report written to {}", path.display());
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, ffi::OsStr, fs, path::PathBuf};
+
+    use super::{Cluster, Known, Mode, Placer, Report, Signature};
+    use crate::report::IceReport;
+
+    #[test]
+    fn test_report() {
+        let ice = IceReport::parse(
+            "thread 'rustc' panicked at compiler/rustc_drice/src/dedup.rs:12:34:
+unexpected type in `foo`
+stack backtrace:
+   0: rustc_drice::dedup::check
+   1: rustc_drice::dedup::run
+",
+        )
+        .unwrap();
+        let report = Report {
+            clusters: vec![Cluster {
+                representative: PathBuf::from("fuzz/a.rs"),
+                members: vec![PathBuf::from("fuzz/dups/b.rs")],
+                ices: vec![Signature::new(&ice)],
+            }],
+            known: vec![Known {
+                file: PathBuf::from("fuzz/c.rs"),
+                dest: PathBuf::from("fuzz/known/123.rs"),
+                entry: "ice/123.rs",
+            }],
+            ok: vec![PathBuf::from("fuzz/ok/d.rs")],
+            hang: Vec::new(),
+            oom: vec![PathBuf::from("fuzz/oom/e.rs")],
+        };
+        assert_eq!(
+            report.summary(),
+            "1 unique ICE(s) with 1 duplicate(s), 1 known, 1 not an ICE, 0 hang(s), 1 out of memory
+fuzz/a.rs (1 duplicate(s)): compiler/rustc_drice/src/dedup.rs:12:34: unexpected type in `_`
+  fuzz/dups/b.rs
+fuzz/c.rs: known, ice/123.rs"
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("dedup.json");
+        report.write(&path).unwrap();
+        let json: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        let cluster = &json["clusters"][0];
+        assert_eq!(cluster["representative"], "fuzz/a.rs");
+        assert_eq!(cluster["members"][0], "fuzz/dups/b.rs");
+        assert_eq!(
+            cluster["ices"][0]["location"],
+            "compiler/rustc_drice/src/dedup.rs:12:34"
+        );
+        assert_eq!(cluster["ices"][0]["functions"].as_array().unwrap().len(), 2);
+        assert_eq!(json["known"][0]["entry"], "ice/123.rs");
+        assert_eq!(json["known"][0]["dest"], "fuzz/known/123.rs");
+        assert_eq!(json["ok"][0], "fuzz/ok/d.rs");
+        assert_eq!(json["hang"].as_array().unwrap().len(), 0);
+        assert_eq!(json["oom"][0], "fuzz/oom/e.rs");
+    }
+
+    #[test]
+    fn test_place_does_not_overwrite() {
+        let dir = tempfile::tempdir().unwrap();
+        let known = dir.path().join("known");
+        fs::create_dir(&known).unwrap();
+        let mut placer = Placer {
+            mode: Mode::Move,
+            dry_run: false,
+            taken: HashSet::new(),
+        };
+        for (file, content) in [("a.rs", "a"), ("b.rs", "b")] {
+            let path = dir.path().join(file);
+            fs::write(&path, content).unwrap();
+            placer
+                .place(&path, &known, Some(OsStr::new("123.rs")))
+                .unwrap();
+        }
+        assert_eq!(fs::read_to_string(known.join("123.rs")).unwrap(), "a");
+        assert_eq!(fs::read_to_string(known.join("123-1.rs")).unwrap(), "b");
+
+        placer.dry_run = true;
+        let path = dir.path().join("c.rs");
+        fs::write(&path, "c").unwrap();
+        let dest = placer.place(&path, &known, Some(OsStr::new("123.rs")));
+        assert_eq!(dest.unwrap(), known.join("123-2.rs"));
+        assert!(path.exists());
+    }
+}
@@ -0,0 +1,292 @@
+//! Parsing of compiletest-style `//@` directives.
+//!
+//! See <https://rustc-dev-guide.rust-lang.org/tests/directives.html>.
+
+use std::path::Path;
+
+use tracing::debug;
+
+/// Directives relevant to running `rustc` on a reproducer.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct Directives {
+    pub(crate) compile_flags: Vec<String>,
+    pub(crate) edition: Option<String>,
+    pub(crate) revision: Option<String>,
+    /// `only-*`, `ignore-*` and `needs-*` directives
+    pub(crate) gates: Vec<String>,
+    pub(crate) known_bug: Option<String>,
+    pub(crate) aux: Vec<Aux>,
+}
+
+/// An auxiliary crate, from `aux-build`, `aux-crate` or `proc-macro`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Aux {
+    /// Path relative to the `auxiliary` directory next to the reproducer
+    pub(crate) file: String,
+    pub(crate) crate_name: String,
+    pub(crate) proc_macro: bool,
+    /// Whether to pass `--extern`, rather than relying on `extern crate`
+    pub(crate) is_extern: bool,
+}
+
+/// Crate name of an auxiliary crate without an explicit one, like compiletest
+fn aux_crate_name(file: &str) -> String {
+    Path::new(file)
+        .file_stem()
+        .map_or_else(String::new, |s| s.to_string_lossy().replace('-', "_"))
+}
+
+impl Directives {
+    #[must_use]
+    pub(crate) fn parse(code: &str) -> Self {
+        let mut directives = Self::default();
+        let lines = code.lines().filter_map(directive_line).collect::<Vec<_>>();
+
+        // Like compiletest, run the first revision (along with any
+        // un-prefixed directives).
+        for (rev, line) in &lines {
+            if rev.is_none()
+                && let Some(("revisions", value)) = split(line)
+            {
+                directives.revision = value.split_whitespace().next().map(str::to_owned);
+            }
+        }
+
+        for (rev, line) in lines {
+            if rev.is_some() && rev != directives.revision.as_deref() {
+                continue;
+            }
+            let Some((name, value)) = split(line) else {
+                continue;
+            };
+            match name {
+                "compile-flags" => directives
+                    .compile_flags
+                    .extend(value.split_whitespace().map(str::to_owned)),
+                "edition" => directives.edition = Some(value.to_owned()),
+                "known-bug" => directives.known_bug = Some(value.to_owned()),
+                "aux-build" | "proc-macro" => directives.aux.push(Aux {
+                    file: value.to_owned(),
+                    crate_name: aux_crate_name(value),
+                    proc_macro: name == "proc-macro",
+                    is_extern: name == "proc-macro",
+                }),
+                // `[priv:][noprelude:]name=file`
+                "aux-crate" => {
+                    let value = value.trim_start_matches("priv:");
+                    let value = value.trim_start_matches("noprelude:");
+                    if let Some((crate_name, file)) = value.split_once('=') {
+                        directives.aux.push(Aux {
+                            file: file.trim().to_owned(),
+                            crate_name: crate_name.trim().to_owned(),
+                            proc_macro: false,
+                            is_extern: true,
+                        });
+                    }
+                }
+                _ if name.starts_with("only-")
+                    || name.starts_with("ignore-")
+                    || name.starts_with("needs-") =>
+                {
+                    if value.is_empty() {
+                        directives.gates.push(name.to_owned());
+                    } else {
+                        directives.gates.push(format!("{name}: {value}"));
+                    }
+                }
+                _ => debug!("ignoring directive `{name}`"),
+            }
+        }
+        directives
+    }
+
+    /// Arguments to pass to `rustc`
+    #[must_use]
+    pub(crate) fn args(&self) -> Vec<String> {
+        let mut args = Vec::with_capacity(self.compile_flags.len() + 2);
+        if let Some(edition) = &self.edition {
+            args.push(format!("--edition={edition}"));
+        }
+        if let Some(rev) = &self.revision {
+            args.push(format!("--cfg={rev}"));
+        }
+        args.extend(self.compile_flags.iter().cloned());
+        args
+    }
+
+    /// Gates that are known not to hold on this host
+    #[must_use]
+    pub(crate) fn unsatisfied_gates(&self) -> Vec<&str> {
+        self.gates
+            .iter()
+            .map(String::as_str)
+            .filter(|gate| gate_holds(gate) == Some(false))
+            .collect()
+    }
+}
+
+/// Returns the revision (if any) and the rest of a `//@` line.
+fn directive_line(line: &str) -> Option<(Option<&str>, &str)> {
+    let line = line.trim_start().strip_prefix("//@")?.trim();
+    if let Some(rest) = line.strip_prefix('[') {
+        let end = rest.find(']')?;
+        return Some((Some(&rest[..end]), rest[end + 1..].trim()));
+    }
+    Some((None, line))
+}
+
+/// Split a directive into name and value. Trailing comments like
+/// `//@ ignore-apple (reason)` are dropped for value-less directives.
+fn split(line: &str) -> Option<(&str, &str)> {
+    let end = line
+        .find(|c: char| c == ':' || c.is_whitespace())
+        .unwrap_or(line.len());
+    let name = &line[..end];
+    if name.is_empty() {
+        return None;
+    }
+    let value = line[end..]
+        .trim_start()
+        .strip_prefix(':')
+        .map_or("", str::trim);
+    Some((name, value))
+}
+
+/// Architectures where `asm!` is stable, see `needs-asm-support`
+const ASM_ARCHES: &[&str] = &[
+    "x86",
+    "x86_64",
+    "arm",
+    "aarch64",
+    "riscv32",
+    "riscv64",
+    "loongarch64",
+    "s390x",
+];
+
+fn matches_host(target: &str) -> Option<bool> {
+    use std::env::consts::{ARCH, FAMILY, OS};
+    match target {
+        "64bit" => Some(cfg!(target_pointer_width = "64")),
+        "32bit" => Some(cfg!(target_pointer_width = "32")),
+        "16bit" => Some(cfg!(target_pointer_width = "16")),
+        "apple" => Some(cfg!(target_vendor = "apple")),
+        "msvc" => Some(cfg!(target_env = "msvc")),
+        "gnu" => Some(cfg!(target_env = "gnu")),
+        "musl" => Some(cfg!(target_env = "musl")),
+        _ if target == ARCH || target == OS || target == FAMILY => Some(true),
+        "x86" | "x86_64" | "arm" | "aarch64" | "riscv32" | "riscv64" | "loongarch64" | "s390x"
+        | "powerpc" | "powerpc64" | "mips" | "mips64" | "wasm32" | "wasm64" | "linux"
+        | "windows" | "macos" | "ios" | "android" | "freebsd" | "netbsd" | "openbsd"
+        | "illumos" | "solaris" | "unix" | "wasm" => Some(false),
+        _ => None,
+    }
+}
+
+/// Whether a gate holds on the host, or `None` if that can't be determined.
+///
+/// This uses the target `drice` was built for, which is assumed to be the
+/// host of the `rustc` it runs.
+fn gate_holds(gate: &str) -> Option<bool> {
+    let (name, value) = split(gate)?;
+    if name == "ignore-backends" {
+        return Some(!value.split_whitespace().any(|b| b == "llvm"));
+    }
+    if let Some(target) = name.strip_prefix("only-") {
+        return matches_host(target);
+    }
+    if let Some(target) = name.strip_prefix("ignore-") {
+        return matches_host(target).map(|m| !m);
+    }
+    match name {
+        "needs-asm-support" => Some(ASM_ARCHES.contains(&std::env::consts::ARCH)),
+        "needs-unwind" => Some(!cfg!(panic = "abort")),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Directives;
+
+    #[test]
+    fn test_parse_compile_flags() {
+        let d = Directives::parse(
+            "//@ known-bug: #123\n//@compile-flags: -Copt-level=3\n//@ compile-flags: -Znext-solver=coherence -Zvalidate-mir\nfn main() {}\n",
+        );
+        assert_eq!(d.known_bug.as_deref(), Some("#123"));
+        assert_eq!(
+            d.compile_flags,
+            ["-Copt-level=3", "-Znext-solver=coherence", "-Zvalidate-mir"]
+        );
+    }
+
+    #[test]
+    fn test_parse_edition() {
+        for src in ["//@ edition: 2021", "//@edition:2021", "//@ edition:2021"] {
+            assert_eq!(Directives::parse(src).args(), ["--edition=2021"]);
+        }
+    }
+
+    #[test]
+    fn test_parse_gates() {
+        let d = Directives::parse(
+            "//@ only-64bit\n//@ ignore-apple (raw-dylib doesn't work on Apple targets yet)\n//@ ignore-backends: gcc\n//@ needs-rustc-debug-assertions\n",
+        );
+        assert_eq!(
+            d.gates,
+            [
+                "only-64bit",
+                "ignore-apple",
+                "ignore-backends: gcc",
+                "needs-rustc-debug-assertions"
+            ]
+        );
+        if cfg!(all(
+            target_pointer_width = "64",
+            not(target_vendor = "apple")
+        )) {
+            assert!(d.unsatisfied_gates().is_empty());
+        }
+        let d = Directives::parse("//@ only-16bit\n//@ ignore-backends: llvm gcc\n");
+        assert_eq!(
+            d.unsatisfied_gates(),
+            ["only-16bit", "ignore-backends: llvm gcc"]
+        );
+    }
+
+    #[test]
+    fn test_parse_aux() {
+        let d = Directives::parse(
+            "//@ aux-build: my-aux.rs\n//@ aux-crate: priv:dep=dep.rs\n//@ proc-macro: derive.rs\n",
+        );
+        let aux = d
+            .aux
+            .iter()
+            .map(|a| {
+                (
+                    a.file.as_str(),
+                    a.crate_name.as_str(),
+                    a.proc_macro,
+                    a.is_extern,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            aux,
+            [
+                ("my-aux.rs", "my_aux", false, false),
+                ("dep.rs", "dep", false, true),
+                ("derive.rs", "derive", true, true),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_revisions() {
+        let d = Directives::parse(
+            "//@ revisions: current next\n//@[next] compile-flags: -Znext-solver\n//@[current] compile-flags: -Zvalidate-mir\n",
+        );
+        assert_eq!(d.args(), ["--cfg=current", "-Zvalidate-mir"]);
+    }
+}
@@ -0,0 +1,255 @@
+//! Why an ICE is or isn't a duplicate of a known one, criterion by criterion,
+//! following [`crate::check::same_report`].
+
+use std::{fmt, fmt::Write as _};
+
+use crate::{
+    check::{query_stack_shape, same_origin, same_report, same_signature},
+    meta::MatchRules,
+    normalize,
+    report::{IceKind, IceReport},
+};
+
+/// The outcome of a criterion
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Verdict {
+    Match,
+    Mismatch,
+    /// Not considered, for this reason
+    Skipped(&'static str),
+}
+
+impl Verdict {
+    fn of(matched: bool) -> Self {
+        if matched { Self::Match } else { Self::Mismatch }
+    }
+}
+
+impl fmt::Display for Verdict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Match => write!(f, "match"),
+            Self::Mismatch => write!(f, "MISMATCH"),
+            Self::Skipped(reason) => write!(f, "skipped ({reason})"),
+        }
+    }
+}
+
+/// A line diff of `l` and `r`: lines only in `l` start with `-`, lines only
+/// in `r` with `+`
+fn diff<'a>(l: &'a [String], r: &'a [String]) -> Vec<(char, &'a str)> {
+    // lcs[i][j]: length of the longest common subsequence of l[i..] and r[j..]
+    let mut lcs = vec![vec![0_usize; r.len() + 1]; l.len() + 1];
+    for i in (0..l.len()).rev() {
+        for j in (0..r.len()).rev() {
+            lcs[i][j] = if l[i] == r[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+    let mut lines = Vec::with_capacity(l.len().max(r.len()));
+    let (mut i, mut j) = (0, 0);
+    while i < l.len() || j < r.len() {
+        if i < l.len() && j < r.len() && l[i] == r[j] {
+            lines.push((' ', l[i].as_str()));
+            i += 1;
+            j += 1;
+        } else if j == r.len() || (i < l.len() && lcs[i + 1][j] >= lcs[i][j + 1]) {
+            lines.push(('-', l[i].as_str()));
+            i += 1;
+        } else {
+            lines.push(('+', r[j].as_str()));
+            j += 1;
+        }
+    }
+    lines
+}
+
+fn kind(report: &IceReport) -> &'static str {
+    match report.kind {
+        IceKind::Panic => "panic",
+        IceKind::Bug => "bug",
+        IceKind::DelayedBug => "delayed bug",
+        IceKind::Segfault => "segfault",
+    }
+}
+
+/// The location, with the kind of ICE and the function and caller that
+/// [`same_origin`] falls back to
+fn origin(report: &IceReport) -> String {
+    let Some(loc) = &report.location else {
+        return format!("(no location, {})", kind(report));
+    };
+    let mut s = format!(
+        "{}:{}:{} ({})",
+        loc.source_path(),
+        loc.line,
+        loc.column,
+        kind(report)
+    );
+    let functions = report.functions();
+    if !functions.is_empty() {
+        let _ = write!(
+            s,
+            " in {}",
+            functions[..functions.len().min(2)].join(" <- ")
+        );
+    }
+    s
+}
+
+fn signature(report: &IceReport) -> String {
+    let signature = report.signature();
+    if signature.is_empty() {
+        String::from("(no backtrace)")
+    } else {
+        signature.join(" <- ")
+    }
+}
+
+fn criterion(out: &mut String, name: &str, verdict: Verdict, l: &str, r: &str) {
+    let _ = writeln!(out, "  {name}: {verdict}");
+    let _ = writeln!(out, "    input: {l}");
+    let _ = writeln!(out, "    entry: {r}");
+}
+
+/// Explain whether the ICE `l` is the ICE `r` of a known entry, with the
+/// `rules` of the entry
+#[must_use]
+pub(crate) fn explain(l: &IceReport, r: &IceReport, rules: &MatchRules) -> String {
+    let mut out = String::new();
+
+    let l_stack = query_stack_shape(l);
+    let r_stack = query_stack_shape(r);
+    let verdict = if rules.ignore_query_stack {
+        Verdict::Skipped("ignore-query-stack")
+    } else {
+        Verdict::of(l_stack == r_stack)
+    };
+    let _ = writeln!(out, "  query stack: {verdict}");
+    let lines = |stack: Option<Vec<(&str, String)>>| {
+        stack.map(|stack| {
+            stack
+                .into_iter()
+                .map(|(query, desc)| format!("[{query}] {desc}"))
+                .collect::<Vec<_>>()
+        })
+    };
+    match (lines(l_stack.clone()), lines(r_stack)) {
+        (None, None) => {
+            let _ = writeln!(out, "    (none)");
+        }
+        (l_lines, r_lines) => {
+            let (l_lines, r_lines) = (l_lines.unwrap_or_default(), r_lines.unwrap_or_default());
+            for (sign, line) in diff(&l_lines, &r_lines) {
+                let _ = writeln!(out, "    {sign} {line}");
+            }
+        }
+    }
+
+    let has_locations = l.location.is_some() && r.location.is_some();
+    let verdict = if has_locations {
+        Verdict::of(same_origin(l, r))
+    } else {
+        Verdict::Skipped("compared by backtrace")
+    };
+    criterion(&mut out, "location", verdict, &origin(l), &origin(r));
+
+    let verdict = if !has_locations || l_stack.is_none() {
+        Verdict::of(same_signature(l, r))
+    } else {
+        Verdict::Skipped("location and query stack suffice")
+    };
+    criterion(&mut out, "backtrace", verdict, &signature(l), &signature(r));
+
+    let l_msg = l.message.as_deref().unwrap_or("(none)");
+    if let Some(re) = &rules.message {
+        let verdict = Verdict::of(re.is_match(l_msg) && l.message.is_some());
+        criterion(&mut out, "message", verdict, l_msg, &format!("/{re}/"));
+    } else if let Some(l_norm) = l.normalized_message()
+        && let Some(r_norm) = r.normalized_message()
+    {
+        let l_shape = normalize::shape(&l_norm);
+        let r_shape = normalize::shape(&r_norm);
+        criterion(
+            &mut out,
+            "message",
+            Verdict::of(l_shape == r_shape),
+            &l_shape,
+            &r_shape,
+        );
+    } else {
+        let r_msg = r.message.as_deref().unwrap_or("(none)");
+        criterion(
+            &mut out,
+            "message",
+            Verdict::Skipped("missing"),
+            l_msg,
+            r_msg,
+        );
+    }
+
+    let _ = write!(
+        out,
+        "  => {}",
+        if same_report(l, r, rules) {
+            "same ICE"
+        } else {
+            "different ICE"
+        }
+    );
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{diff, explain};
+    use crate::{meta::MatchRules, report::IceReport};
+
+    #[test]
+    fn test_diff() {
+        let lines = |s: &str| s.split(' ').map(String::from).collect::<Vec<_>>();
+        assert_eq!(
+            diff(&lines("a b c d"), &lines("a c e d")),
+            [(' ', "a"), ('-', "b"), (' ', "c"), ('+', "e"), (' ', "d")]
+        );
+        assert_eq!(diff(&[], &lines("a")), [('+', "a")]);
+    }
+
+    const ICE: &str = "\
+thread 'rustc' panicked at compiler/rustc_drice/src/explain.rs:12:34:
+unexpected type in `foo`
+stack backtrace:
+   0: rustc_drice::explain::check
+   1: rustc_drice::explain::run
+
+error: the compiler unexpectedly panicked. this is a bug.
+
+query stack during panic:
+#0 [type_of] computing type of `foo`
+#1 [analysis] running analysis passes on this crate
+end of query stack
+";
+
+    #[test]
+    fn test_explain() {
+        let l = IceReport::parse(&ICE.replace("#0 [type_of]", "#0 [drice]")).unwrap();
+        let r = IceReport::parse(ICE).unwrap();
+        let explained = explain(&l, &r, &MatchRules::default());
+        assert!(explained.contains("query stack: MISMATCH"), "{explained}");
+        assert!(explained.contains("    - [drice] "), "{explained}");
+        assert!(explained.contains("    + [type_of] "), "{explained}");
+        assert!(explained.contains("location: match"), "{explained}");
+        assert!(explained.ends_with("=> different ICE"), "{explained}");
+        let rules = MatchRules {
+            ignore_query_stack: true,
+            message: None,
+        };
+        assert!(explain(&l, &r, &rules).ends_with("=> same ICE"));
+        let same = explain(&r, &r, &MatchRules::default());
+        assert!(same.contains("query stack: match"), "{same}");
+        assert!(same.ends_with("=> same ICE"), "{same}");
+    }
+}
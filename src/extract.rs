@@ -1,14 +1,15 @@
 use std::fs;
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
 use anyhow::Context;
 
-use crate::{check, rustc};
+use crate::{check, db, directive::Directives, rustc};
 
 pub(crate) struct ExtractConfig {
     pub issue_or_path: String,
+    pub rustc: rustc::RustcConfig,
 }
 
 fn extract_rust_code_block(markdown: &str) -> Option<String> {
@@ -31,7 +32,10 @@ fn extract_rust_code_block(markdown: &str) -> Option<String> {
     None
 }
 
-pub(crate) fn run_rustc_on_temp(code: &str) -> anyhow::Result<String> {
+pub(crate) fn run_rustc_on_temp(
+    config: &rustc::RustcConfig,
+    code: &str,
+) -> anyhow::Result<rustc::Output> {
     let mut temp_file =
         tempfile::NamedTempFile::with_suffix(".rs").context("failed to create temporary file")?;
     temp_file
@@ -40,7 +44,7 @@ pub(crate) fn run_rustc_on_temp(code: &str) -> anyhow::Result<String> {
     temp_file
         .flush()
         .context("failed to flush temporary file")?;
-    rustc::go(temp_file.path()).with_context(|| {
+    rustc::go(config, temp_file.path()).with_context(|| {
         format!(
             "failed to run rustc on temporary file: {}",
             temp_file.path().display()
@@ -60,12 +64,18 @@ pub(crate) fn extract(config: ExtractConfig) -> anyhow::Result<()> {
     }
 
     let path = PathBuf::from(&config.issue_or_path);
-    let (code, stderr) = if path.exists() {
+    if path.is_dir() {
+        anyhow::bail!(
+            "{}: only single files can be saved to ice/, see `drice check`",
+            path.display()
+        );
+    }
+    let (code, output) = if path.exists() {
         let code = fs::read_to_string(path.as_path())
             .with_context(|| format!("failed to read file: {}", path.display()))?;
         (
             code,
-            rustc::go(path.as_path())
+            rustc::go(&config.rustc, path.as_path())
                 .with_context(|| format!("failed to run rustc on file: {}", path.display()))?,
         )
     } else {
@@ -90,40 +100,73 @@ pub(crate) fn extract(config: ExtractConfig) -> anyhow::Result<()> {
 
         let issue_body = String::from_utf8_lossy(&output.stdout);
         let code = extract_rust_code_block(&issue_body).ok_or(anyhow::anyhow!("No code block"))?;
-        let stderr = run_rustc_on_temp(&code)?;
-        (code, stderr)
+        let output = run_rustc_on_temp(&config.rustc, &code)?;
+        (code, output)
     };
 
-    if check::is_ice(stderr.as_str()).is_none() {
+    if output.exit == rustc::Exit::Timeout {
+        anyhow::bail!("rustc timed out");
+    }
+    if !Directives::parse(&code).aux.is_empty() {
+        anyhow::bail!("programs with auxiliary crates can't be saved to ice/");
+    }
+    let text = output.text().into_owned();
+    let invocation = output.invocation();
+    let stderr = output.stderr;
+
+    if check::is_ice(text.as_str()).is_none() {
         return Err(anyhow::anyhow!("Not an ICE:\n{stderr}"));
     }
-    if let Some(existing) = check::exists(&stderr) {
+    if let Some(existing) = check::exists(&text) {
         eprintln!("Duplicate of {existing}");
-        let path = PathBuf::from(existing);
-        let mut base =
-            PathBuf::from(path.file_name().with_context(|| {
-                format!("failed to get file name from path: {}", path.display())
-            })?);
-        let dups = PathBuf::from("ice/dup");
-        let mut dup_rs_path = dups.clone();
-        dup_rs_path.push(&base);
-        let mut dup_out_path = dups.clone();
-        base.set_extension("out");
-        dup_out_path.push(base);
-        if !dup_rs_path.exists() || !dup_out_path.exists() {
-            fs::write(&dup_rs_path, code).with_context(|| {
-                format!(
-                    "failed to write duplicate RS file: {}",
-                    dup_rs_path.display()
-                )
-            })?;
-            fs::write(&dup_out_path, stderr).with_context(|| {
-                format!(
-                    "failed to write duplicate OUT file: {}",
-                    dup_out_path.display()
-                )
-            })?;
+        let name = db::entry_name(existing);
+        // Next to the entry, which may be in another database (see `--db`)
+        let dup_dir = Path::new(existing).with_file_name("dup");
+        let mut reproducers = vec![PathBuf::from(existing)];
+        reproducers.extend(
+            db::dups_of(&dup_dir, &name)?
+                .iter()
+                .map(|dup| dup_dir.join(format!("{dup}.rs"))),
+        );
+        if reproducers
+            .iter()
+            .any(|rs| fs::read_to_string(rs).is_ok_and(|known| known.trim() == code.trim()))
+        {
+            return Ok(());
         }
+
+        fs::create_dir_all(&dup_dir)
+            .with_context(|| format!("failed to create directory: {}", dup_dir.display()))?;
+        let dup_rs_path = dup_dir.join(format!("{}.rs", db::free_dup_name(&dup_dir, &name)));
+        let dup_out_path = dup_rs_path.with_extension("out");
+        let dup_toml_path = dup_rs_path.with_extension("toml");
+        fs::write(&dup_rs_path, code).with_context(|| {
+            format!(
+                "failed to write duplicate RS file: {}",
+                dup_rs_path.display()
+            )
+        })?;
+        fs::write(&dup_out_path, stderr).with_context(|| {
+            format!(
+                "failed to write duplicate OUT file: {}",
+                dup_out_path.display()
+            )
+        })?;
+        invocation.write(&dup_out_path)?;
+        let provenance = if path.exists() {
+            path.display().to_string()
+        } else {
+            format!("rust-lang/rust#{}", config.issue_or_path)
+        };
+        let mut meta = toml::Table::new();
+        meta.insert("provenance".to_owned(), provenance.into());
+        fs::write(&dup_toml_path, meta.to_string()).with_context(|| {
+            format!(
+                "failed to write duplicate TOML file: {}",
+                dup_toml_path.display()
+            )
+        })?;
+        println!("Saved to {}", dup_rs_path.display());
         return Ok(());
     }
 
@@ -134,6 +177,7 @@ pub(crate) fn extract(config: ExtractConfig) -> anyhow::Result<()> {
         .with_context(|| format!("failed to write RS file: {}", rs_path.display()))?;
     fs::write(&out_path, stderr)
         .with_context(|| format!("failed to write OUT file: {}", out_path.display()))?;
+    invocation.write(&out_path)?;
 
     println!("Saved to {} and {}", rs_path.display(), out_path.display());
     Ok(())
@@ -157,8 +201,8 @@ mod tests {
                 let entry = entry.unwrap();
                 let path = entry.path();
                 if path.extension().and_then(|s| s.to_str()) == Some("rs") {
-                    let stderr = match rustc::go(&path) {
-                        Ok(stderr) => stderr,
+                    let stderr = match rustc::go(&rustc::RustcConfig::default(), &path) {
+                        Ok(output) => output.text().into_owned(),
                         Err(e) => {
                             failures.push(format!(
                                 "{}: Failed to run rustc: {}",
@@ -2,25 +2,51 @@ use std::{fs, path::PathBuf};
 
 use anyhow::Context;
 
-use crate::{check, rustc};
+use crate::{project, report::IceReport, rustc};
 
 pub(crate) struct GlanceConfig {
     pub file: PathBuf,
+    pub rustc: rustc::RustcConfig,
 }
 
 pub(crate) fn glance(config: GlanceConfig) -> anyhow::Result<()> {
-    let content = if config.file.extension().and_then(|s| s.to_str()) == Some("rs") {
-        rustc::go(config.file.as_path())
-            .with_context(|| format!("failed to run rustc on file: {}", config.file.display()))?
+    let (content, invocation) = if project::is_reproducer(&config.file) {
+        let output = rustc::go(&config.rustc, config.file.as_path())
+            .with_context(|| format!("failed to run rustc on: {}", config.file.display()))?;
+        (output.text().into_owned(), Some(output.invocation()))
     } else {
-        fs::read_to_string(config.file.as_path())
-            .with_context(|| format!("failed to read file: {}", config.file.display()))?
+        (
+            fs::read_to_string(config.file.as_path())
+                .with_context(|| format!("failed to read file: {}", config.file.display()))?,
+            rustc::Invocation::read(config.file.as_path())?,
+        )
     };
-    let path = check::extract_file_path(&content);
-    let message = check::extract_message(&content);
-    let stack = check::extract_query_stack(&content);
-    println!("{}", path.as_deref().unwrap_or("(no path)"));
-    println!("{}", message.as_deref().unwrap_or("(no message)"));
-    println!("{}", stack.as_deref().unwrap_or("(no query stack)"));
+    let Some(report) = IceReport::parse(&content) else {
+        println!("(not an ICE)");
+        return Ok(());
+    };
+    match &report.location {
+        Some(location) => println!("{location}"),
+        None => println!("(no path)"),
+    }
+    println!("{}", report.message.as_deref().unwrap_or("(no message)"));
+    match &report.query_stack {
+        Some(stack) => {
+            for (i, frame) in stack.iter().enumerate() {
+                println!("#{i} [{}] {}", frame.query, frame.description);
+            }
+        }
+        None => println!("(no query stack)"),
+    }
+    let signature = report.signature();
+    if signature.is_empty() {
+        println!("(no backtrace)");
+    }
+    for function in signature {
+        println!("in {function}");
+    }
+    if let Some(invocation) = invocation {
+        println!("{invocation}");
+    }
     Ok(())
 }
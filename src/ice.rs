@@ -1,4 +1,7 @@
-// DO NOT EDIT: generated by scripts/update-ices.rs
+// DO NOT EDIT: generated by `drice db reindex`
+#[cfg(not(feature = "embed"))]
+pub(crate) const ICES: &[(&str, &str)] = &[];
+#[cfg(feature = "embed")]
 pub(crate) const ICES: &[(&str, &str)] = &[
     ("ice/100041.rs", include_str!("../ice/100041.out")),
     ("ice/102252.rs", include_str!("../ice/102252.out")),
@@ -131,3 +134,127 @@ pub(crate) const ICES: &[(&str, &str)] = &[
         include_str!("../ice/project-to-simd-array-field.out"),
     ),
 ];
+#[cfg(not(feature = "embed"))]
+pub(crate) const RETIRED: &[(&str, &str)] = &[];
+#[cfg(feature = "embed")]
+pub(crate) const RETIRED: &[(&str, &str)] = &[
+    (
+        "ice/attic/104685.rs",
+        include_str!("../ice/attic/104685.out"),
+    ),
+    (
+        "ice/attic/105238-1.rs",
+        include_str!("../ice/attic/105238-1.out"),
+    ),
+    (
+        "ice/attic/110534.rs",
+        include_str!("../ice/attic/110534.out"),
+    ),
+    (
+        "ice/attic/114198-2.rs",
+        include_str!("../ice/attic/114198-2.out"),
+    ),
+    (
+        "ice/attic/114198.rs",
+        include_str!("../ice/attic/114198.out"),
+    ),
+    (
+        "ice/attic/119786.rs",
+        include_str!("../ice/attic/119786.out"),
+    ),
+    (
+        "ice/attic/120016.rs",
+        include_str!("../ice/attic/120016.out"),
+    ),
+    (
+        "ice/attic/123157.rs",
+        include_str!("../ice/attic/123157.out"),
+    ),
+    (
+        "ice/attic/123810.rs",
+        include_str!("../ice/attic/123810.out"),
+    ),
+    (
+        "ice/attic/124751.rs",
+        include_str!("../ice/attic/124751.out"),
+    ),
+    (
+        "ice/attic/125553.rs",
+        include_str!("../ice/attic/125553.out"),
+    ),
+    (
+        "ice/attic/126680.rs",
+        include_str!("../ice/attic/126680.out"),
+    ),
+    (
+        "ice/attic/127033.rs",
+        include_str!("../ice/attic/127033.out"),
+    ),
+    (
+        "ice/attic/131886.rs",
+        include_str!("../ice/attic/131886.out"),
+    ),
+    (
+        "ice/attic/136188.rs",
+        include_str!("../ice/attic/136188.out"),
+    ),
+    (
+        "ice/attic/136442.rs",
+        include_str!("../ice/attic/136442.out"),
+    ),
+    (
+        "ice/attic/138707.rs",
+        include_str!("../ice/attic/138707.out"),
+    ),
+    (
+        "ice/attic/140303.rs",
+        include_str!("../ice/attic/140303.out"),
+    ),
+    (
+        "ice/attic/140850.rs",
+        include_str!("../ice/attic/140850.out"),
+    ),
+    (
+        "ice/attic/145983.rs",
+        include_str!("../ice/attic/145983.out"),
+    ),
+    (
+        "ice/attic/146371.rs",
+        include_str!("../ice/attic/146371.out"),
+    ),
+];
+#[cfg(not(feature = "embed"))]
+pub(crate) const DUPS: &[(&str, &str)] = &[];
+#[cfg(feature = "embed")]
+pub(crate) const DUPS: &[(&str, &str)] = &[
+    ("ice/dup/100041.rs", include_str!("../ice/dup/100041.out")),
+    ("ice/dup/102252.rs", include_str!("../ice/dup/102252.out")),
+    ("ice/dup/103708.rs", include_str!("../ice/dup/103708.out")),
+    ("ice/dup/110378.rs", include_str!("../ice/dup/110378.out")),
+    ("ice/dup/117392.rs", include_str!("../ice/dup/117392.out")),
+    ("ice/dup/117795.rs", include_str!("../ice/dup/117795.out")),
+    ("ice/dup/120873.rs", include_str!("../ice/dup/120873.out")),
+    ("ice/dup/121858.rs", include_str!("../ice/dup/121858.out")),
+    ("ice/dup/123140.rs", include_str!("../ice/dup/123140.out")),
+    ("ice/dup/123629.rs", include_str!("../ice/dup/123629.out")),
+    ("ice/dup/123690.rs", include_str!("../ice/dup/123690.out")),
+    ("ice/dup/135122.rs", include_str!("../ice/dup/135122.out")),
+    ("ice/dup/135617.rs", include_str!("../ice/dup/135617.out")),
+    ("ice/dup/135720.rs", include_str!("../ice/dup/135720.out")),
+    ("ice/dup/135845.rs", include_str!("../ice/dup/135845.out")),
+    ("ice/dup/136379.rs", include_str!("../ice/dup/136379.out")),
+    (
+        "ice/dup/137190-2.rs",
+        include_str!("../ice/dup/137190-2.out"),
+    ),
+    ("ice/dup/141504.rs", include_str!("../ice/dup/141504.out")),
+    ("ice/dup/143787.rs", include_str!("../ice/dup/143787.out")),
+    ("ice/dup/144241.rs", include_str!("../ice/dup/144241.out")),
+    ("ice/dup/94846.rs", include_str!("../ice/dup/94846.out")),
+];
+pub(crate) const META: &[(&str, &str)] = &[
+    ("ice/122529.rs", include_str!("../ice/122529.toml")),
+    ("ice/127643.rs", include_str!("../ice/127643.toml")),
+    ("ice/135845.rs", include_str!("../ice/135845.toml")),
+    ("ice/144241.rs", include_str!("../ice/144241.toml")),
+];
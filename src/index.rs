@@ -0,0 +1,104 @@
+//! An index of ICEs by where they originated and their query stack, to find
+//! the few that [`crate::check::same_report`] could match without comparing
+//! against all of them.
+//!
+//! Two ICEs are only the same if their query stacks are (unless the rules of
+//! the known one ignore them), and they originated in the same file, or,
+//! without a location, went through the same functions. Lookups return every
+//! ICE that meets these conditions, which still have to be compared.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    hash::{DefaultHasher, Hash, Hasher},
+};
+
+use crate::{check::query_stack_shape, report::IceReport};
+
+fn hash(value: &impl Hash) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// The query stack of an ICE, as far as matching goes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Stack {
+    /// Hash of the shape of the query stack, or of its absence
+    Hash(u64),
+    /// The known ICE matches any query stack
+    Ignored,
+}
+
+impl Stack {
+    fn of(report: &IceReport) -> Self {
+        Self::Hash(hash(&query_stack_shape(report)))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Key {
+    /// The file of the location in the source of `rustc`
+    Location(String, Stack),
+    /// Hash of [`IceReport::signature`]
+    Signature(u64, Stack),
+}
+
+/// ICEs, identified by a `T`, by [`Key`]
+#[derive(Debug)]
+pub(crate) struct SignatureIndex<T> {
+    keys: HashMap<Key, Vec<T>>,
+}
+
+impl<T> Default for SignatureIndex<T> {
+    fn default() -> Self {
+        Self {
+            keys: HashMap::new(),
+        }
+    }
+}
+
+impl<T: Copy + Ord> SignatureIndex<T> {
+    /// Add the ICE `report` as `id`, matching any query stack with
+    /// `ignore_query_stack`
+    pub(crate) fn insert(&mut self, id: T, report: &IceReport, ignore_query_stack: bool) {
+        let stack = if ignore_query_stack {
+            Stack::Ignored
+        } else {
+            Stack::of(report)
+        };
+        if let Some(loc) = &report.location {
+            self.keys
+                .entry(Key::Location(loc.source_path().to_owned(), stack))
+                .or_default()
+                .push(id);
+        }
+        // ICEs with a location are compared by signature to those without
+        self.keys
+            .entry(Key::Signature(hash(&report.signature()), stack))
+            .or_default()
+            .push(id);
+    }
+
+    /// The IDs of the ICEs that may be the same as `report`, in order
+    #[must_use]
+    pub(crate) fn candidates(&self, report: &IceReport) -> Vec<T> {
+        let mut ids = BTreeSet::new();
+        let stacks = [Stack::of(report), Stack::Ignored];
+        let signature = hash(&report.signature());
+        for stack in stacks {
+            if let Some(loc) = &report.location
+                && let Some(found) = self
+                    .keys
+                    .get(&Key::Location(loc.source_path().to_owned(), stack))
+            {
+                ids.extend(found);
+            }
+            // Includes some with a location when `report` has one, which is
+            // harmless
+            if let Some(found) = self.keys.get(&Key::Signature(signature, stack)) {
+                ids.extend(found);
+            }
+        }
+        ids.into_iter().collect()
+    }
+}
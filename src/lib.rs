@@ -1,9 +1,23 @@
 #![allow(dead_code)]
+mod cache;
 mod check;
+mod database;
+mod directive;
+mod explain;
 mod ice;
+mod index;
+mod meta;
+mod normalize;
+mod par;
+mod project;
+mod report;
 mod rustc;
+mod similarity;
 
 pub use check::IceStatus;
 pub use check::IceType;
 pub use check::analyze_ice;
 pub use check::code_uses_internal_features;
+pub use report::{
+    BacktraceFrame, DelayedBug, Diagnostic, IceKind, IceReport, Location, QueryFrame,
+};
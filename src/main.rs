@@ -1,14 +1,30 @@
+use std::{path::PathBuf, time::Duration};
+
+use anyhow::Context as _;
 use clap::Parser as _;
 use tracing::Level;
 use tracing_subscriber::fmt::format::FmtSpan;
 
+pub mod cache;
 pub mod check;
 pub mod cli;
+pub mod database;
+pub mod db;
 pub mod dedup;
+pub mod directive;
+pub mod explain;
 pub mod extract;
 pub mod glance;
 pub mod ice;
+pub mod index;
+pub mod meta;
+pub mod normalize;
+pub mod par;
+pub mod project;
+pub mod rebuild;
+pub mod report;
 pub mod rustc;
+pub mod similarity;
 
 fn verbosity_to_log_level(verbosity: u8) -> Level {
     match verbosity {
@@ -34,25 +50,127 @@ fn init_tracing(cli: &cli::Cli) {
     }
 }
 
+fn cache_dir(args: &cli::RustcArgs) -> anyhow::Result<PathBuf> {
+    args.cache_dir
+        .clone()
+        .or_else(cache::Cache::default_dir)
+        .context("failed to determine cache directory, try --cache-dir")
+}
+
+/// `--memory-limit` in bytes
+fn memory_limit(mib: u64) -> anyhow::Result<u64> {
+    mib.checked_mul(1024 * 1024)
+        .with_context(|| format!("--memory-limit is too large: {mib} MiB"))
+}
+
+fn rustc_config(args: cli::RustcArgs) -> anyhow::Result<rustc::RustcConfig> {
+    let cache = if args.no_cache {
+        None
+    } else {
+        Some(cache::Cache::new(cache_dir(&args)?))
+    };
+    let toolchain = match args.rustc {
+        Some(path) => rustc::Toolchain::Path(path),
+        None => rustc::Toolchain::Rustup(args.toolchain),
+    };
+    Ok(rustc::RustcConfig {
+        toolchain,
+        timeout: (args.timeout != 0).then(|| Duration::from_secs(args.timeout)),
+        memory_limit: args.memory_limit.map(memory_limit).transpose()?,
+        cache,
+    })
+}
+
 fn main() -> anyhow::Result<()> {
     let cli = cli::Cli::parse();
     init_tracing(&cli);
+    database::load(&cli.db)?;
+    if let cli::Command::Cache { command } = &cli.command {
+        let cache = cache::Cache::new(cache_dir(&cli.rustc)?);
+        match command {
+            cli::CacheCommand::Clear => cache.clear()?,
+            cli::CacheCommand::Dir => println!("{}", cache.dir().display()),
+        }
+        return Ok(());
+    }
+    let rustc = rustc_config(cli.rustc)?;
 
     match cli.command {
-        cli::Command::Check { file } => {
-            check::check(check::CheckConfig { file })?;
+        cli::Command::Check {
+            files,
+            jobs,
+            top,
+            explain,
+            against,
+        } => {
+            check::check(check::CheckConfig {
+                files,
+                rustc,
+                jobs: jobs.unwrap_or_else(par::default_jobs),
+                top,
+                explain,
+                against,
+            })?;
         }
         cli::Command::Extract { issue_or_path } => {
-            extract::extract(extract::ExtractConfig { issue_or_path })?;
+            extract::extract(extract::ExtractConfig {
+                issue_or_path,
+                rustc,
+            })?;
         }
         cli::Command::Rustc { path } => {
-            eprintln!("{}", rustc::go(path.as_path())?);
+            let output = rustc::go(&rustc, path.as_path())?;
+            eprintln!("{}", output.stderr);
+            if output.exit == rustc::Exit::Timeout {
+                eprintln!("rustc timed out");
+            }
+        }
+        cli::Command::Dedup {
+            directory,
+            jobs,
+            mode,
+            dry_run,
+        } => {
+            dedup::dedup(dedup::DedupConfig {
+                directory,
+                rustc,
+                jobs: jobs.unwrap_or_else(par::default_jobs),
+                mode,
+                dry_run,
+            })?;
+        }
+        cli::Command::Rebuild { jobs, retire } => {
+            rebuild::rebuild(rebuild::RebuildConfig {
+                rustc,
+                jobs: jobs.unwrap_or_else(par::default_jobs),
+                retire,
+            })?;
         }
-        cli::Command::Dedup { directory } => {
-            dedup::dedup(dedup::DedupConfig { directory })?;
+        cli::Command::Db { dry_run, command } => {
+            match command {
+                cli::DbCommand::Add { files, dup } => {
+                    for file in files {
+                        db::add(&rustc, &file, dup, dry_run)?;
+                    }
+                }
+                cli::DbCommand::Retire { names } => {
+                    for name in names {
+                        db::retire(&db::entry_name(&name), dry_run)?;
+                    }
+                }
+                cli::DbCommand::Normalize { names } => db::normalize(&names, dry_run)?,
+                cli::DbCommand::Regressions { jobs } => {
+                    db::regressions(&rustc, jobs.unwrap_or_else(par::default_jobs), dry_run)?;
+                }
+                cli::DbCommand::Reindex => {}
+            }
+            // Embeds what the above changed, e.g., the outputs that
+            // `Regressions` saved for those in ice/attic/ that ICE again
+            db::reindex(dry_run)?;
         }
+        cli::Command::Cache { .. } => unreachable!("handled above"),
         cli::Command::Glance { file } => {
-            glance::glance(glance::GlanceConfig { file })?;
+            glance::glance(glance::GlanceConfig { file, rustc })?;
         }
     }
 
@@ -61,7 +179,13 @@ fn main() -> anyhow::Result<()> {
 
 #[cfg(test)]
 mod tests {
-    use crate::ice::ICES;
+    use crate::{ice::ICES, memory_limit};
+
+    #[test]
+    fn test_memory_limit() {
+        assert_eq!(memory_limit(2).unwrap(), 2 * 1024 * 1024);
+        assert!(memory_limit(u64::MAX / 1024).is_err());
+    }
 
     #[test]
     fn test_every_rs_file_has_entry_in_ices() {
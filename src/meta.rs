@@ -0,0 +1,357 @@
+//! Optional metadata of entries in the database, in `ice/NNN.toml` next to
+//! `ice/NNN.rs`:
+//!
+//! ```toml
+//! # The issue, by default the number in the name of the entry
+//! issue = 135845
+//! title = "ICE: broken MIR in ..."
+//! status = "open"
+//! labels = ["I-ICE", "T-compiler", "F-generic_const_exprs"]
+//! # What it takes to reproduce, for humans: `drice` runs `rustc` with the
+//! # `//@ compile-flags` of the program only
+//! flags = ["-Znext-solver"]
+//! features = ["generic_const_exprs"]
+//! # Where it came from, when it was added and the first nightly it ICEs on
+//! provenance = "fuzzer"
+//! added = "2025-06-01"
+//! first-seen = "nightly-2025-05-30"
+//! # Other entries of the same database, by name
+//! same-root-cause = ["127643"]
+//! known-ambiguous = ["141504"]
+//!
+//! # How to match other ICEs against this one, after the keys above, which
+//! # would otherwise belong to this table
+//! [match]
+//! ignore-query-stack = true
+//! message = "^broken MIR in .* bad assignment"
+//! ```
+//!
+//! Relations are symmetric, and only need to be declared on one of the entries.
+//! `drice db normalize` derives the `//@ known-bug` header of the entry from
+//! the issue.
+
+use std::{fmt, fmt::Write as _, path::Path, sync::LazyLock};
+
+use anyhow::Context;
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::database;
+
+/// Overrides for matching ICEs against an entry
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub(crate) struct MatchRules {
+    /// Don't compare query stacks, e.g., as they depend on the program
+    #[serde(default)]
+    pub(crate) ignore_query_stack: bool,
+    /// Compare messages by matching them against this rather than against the
+    /// message of the entry
+    #[serde(default, deserialize_with = "regex")]
+    pub(crate) message: Option<Regex>,
+}
+
+fn regex<'de, D: serde::Deserializer<'de>>(d: D) -> Result<Option<Regex>, D::Error> {
+    let Some(s) = Option::<String>::deserialize(d)? else {
+        return Ok(None);
+    };
+    Regex::new(&s).map(Some).map_err(serde::de::Error::custom)
+}
+
+/// Status of the issue of an entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum Status {
+    Open,
+    Closed,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Open => write!(f, "open"),
+            Self::Closed => write!(f, "closed"),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub(crate) struct Meta {
+    /// Number of the issue on `rust-lang/rust`, see [`Meta::issue`]
+    #[serde(default)]
+    pub(crate) issue: Option<u32>,
+    #[serde(default)]
+    pub(crate) title: Option<String>,
+    #[serde(default)]
+    pub(crate) status: Option<Status>,
+    #[serde(default)]
+    pub(crate) labels: Vec<String>,
+    /// Flags that `rustc` needs to ICE, for information only: `drice` passes
+    /// those in `//@ compile-flags` of the program, see
+    /// [`crate::directive::Directives`]
+    #[serde(default)]
+    pub(crate) flags: Vec<String>,
+    /// Features that the program enables
+    #[serde(default)]
+    pub(crate) features: Vec<String>,
+    /// Where the program came from, e.g., `tests/crashes` or a fuzzer
+    #[serde(default)]
+    pub(crate) provenance: Option<String>,
+    /// When the entry was added, like `2025-06-01`
+    #[serde(default)]
+    pub(crate) added: Option<String>,
+    /// The first nightly that ICEs, like `nightly-2025-05-30`
+    #[serde(default)]
+    pub(crate) first_seen: Option<String>,
+    #[serde(default, rename = "match")]
+    pub(crate) rules: MatchRules,
+    /// Entries for the same bug, e.g., reported in several issues
+    #[serde(default)]
+    pub(crate) same_root_cause: Vec<String>,
+    /// Entries that `drice` can't tell apart from this one, though they may
+    /// be distinct bugs
+    #[serde(default)]
+    pub(crate) known_ambiguous: Vec<String>,
+}
+
+impl Meta {
+    pub(crate) fn parse(path: &str, text: &str) -> anyhow::Result<Self> {
+        toml::from_str(text).with_context(|| format!("failed to parse metadata: {path}"))
+    }
+
+    /// The issue of entry `path`, or else the number in its name, like `123`
+    /// for `ice/123.rs` and `ice/123-2.rs`
+    #[must_use]
+    pub(crate) fn issue(&self, path: &str) -> Option<u32> {
+        self.issue.or_else(|| {
+            let name = name(path);
+            name.split_once('-').map_or(name, |(n, _)| n).parse().ok()
+        })
+    }
+
+    /// The `//@ known-bug` header of entry `path`
+    #[must_use]
+    pub(crate) fn known_bug(&self, path: &str) -> Option<String> {
+        self.issue(path)
+            .map(|issue| format!("//@ known-bug: #{issue}"))
+    }
+
+    /// A description of entry `path` for humans, one line per aspect
+    #[must_use]
+    pub(crate) fn describe(&self, path: &str) -> Vec<String> {
+        let mut lines = Vec::new();
+        if let Some(issue) = self.issue(path) {
+            let mut line = format!("issue: rust-lang/rust#{issue}");
+            if let Some(status) = self.status {
+                let _ = write!(line, " ({status})");
+            }
+            if let Some(title) = &self.title {
+                let _ = write!(line, ": {title}");
+            }
+            lines.push(line);
+        }
+        if !self.labels.is_empty() {
+            lines.push(format!("labels: {}", self.labels.join(", ")));
+        }
+        let requires: Vec<_> = self
+            .flags
+            .iter()
+            .cloned()
+            .chain(self.features.iter().map(|f| format!("feature({f})")))
+            .collect();
+        if !requires.is_empty() {
+            lines.push(format!("requires: {}", requires.join(", ")));
+        }
+        let mut history = Vec::new();
+        if let Some(provenance) = &self.provenance {
+            history.push(format!("from {provenance}"));
+        }
+        if let Some(added) = &self.added {
+            history.push(format!("added {added}"));
+        }
+        if let Some(first_seen) = &self.first_seen {
+            history.push(format!("first seen on {first_seen}"));
+        }
+        if !history.is_empty() {
+            lines.push(history.join(", "));
+        }
+        lines
+    }
+}
+
+/// How two entries are related, see [`Meta`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Relation {
+    SameRootCause,
+    KnownAmbiguous,
+}
+
+impl fmt::Display for Relation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::SameRootCause => write!(f, "same root cause as"),
+            Self::KnownAmbiguous => write!(f, "known to be ambiguous with"),
+        }
+    }
+}
+
+/// Name of the entry for `path`, like `123` for `ice/123.rs`
+pub(crate) fn name(path: &str) -> &str {
+    Path::new(path)
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or(path)
+}
+
+/// The metadata of the known and retired ICEs (see [`database::entries`] and
+/// [`database::retired`]) and their alternate reproducers, by path, parsed
+/// once
+static PARSED: LazyLock<Vec<(&'static str, Meta)>> = LazyLock::new(|| {
+    database::entries()
+        .into_iter()
+        .chain(database::retired())
+        .flat_map(|entry| {
+            let dups = entry.dups.into_iter().map(|dup| (dup.name, dup.meta));
+            [(entry.name, entry.meta)].into_iter().chain(dups)
+        })
+        .filter_map(|(name, text)| match Meta::parse(name, text?) {
+            Ok(meta) => Some((name, meta)),
+            Err(e) => {
+                tracing::warn!("{e:#}");
+                None
+            }
+        })
+        .collect()
+});
+
+static DEFAULT: LazyLock<Meta> = LazyLock::new(Meta::default);
+
+/// The metadata of entry `path`, like `ice/123.rs`. Entries of other
+/// databases with the same name have their own (or none).
+pub(crate) fn get(path: &str) -> &'static Meta {
+    PARSED
+        .iter()
+        .find(|(nm, _)| *nm == path)
+        .map_or(&DEFAULT, |(_, meta)| meta)
+}
+
+/// How `meta` of entry `from` declares it relates to entry `to`. Names in
+/// `meta` refer to entries of the same database as `from`.
+fn declared(meta: &Meta, from: &str, to: &str) -> Option<Relation> {
+    let refers = |names: &[String]| {
+        names
+            .iter()
+            .any(|n| Path::new(from).with_file_name(format!("{n}.rs")) == Path::new(to))
+    };
+    if refers(&meta.same_root_cause) {
+        Some(Relation::SameRootCause)
+    } else if refers(&meta.known_ambiguous) {
+        Some(Relation::KnownAmbiguous)
+    } else {
+        None
+    }
+}
+
+/// How entries `l` and `r` (like `ice/123.rs`) are related, if at all
+pub(crate) fn relation(l: &str, r: &str) -> Option<Relation> {
+    declared(get(l), l, r).or_else(|| declared(get(r), r, l))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use super::{Meta, Relation, declared, name};
+    use crate::ice::META;
+
+    #[test]
+    fn test_meta_valid() {
+        for (path, text) in META {
+            let meta = Meta::parse(path, text).unwrap();
+            for other in meta.same_root_cause.iter().chain(&meta.known_ambiguous) {
+                let other_path = Path::new(path).with_file_name(format!("{other}.rs"));
+                assert!(
+                    other_path.exists(),
+                    "{path} refers to {other}, which isn't in the database"
+                );
+                assert_ne!(name(path), other, "{path} refers to itself");
+            }
+        }
+    }
+
+    /// The ```` ```toml ```` block in `text`, with `prefix` stripped from its lines
+    fn example(text: &str, prefix: &str) -> String {
+        text.lines()
+            .map(|line| line.strip_prefix(prefix).unwrap_or(line).trim_start())
+            .skip_while(|line| *line != "```toml")
+            .skip(1)
+            .take_while(|line| *line != "```")
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    #[test]
+    fn test_meta_examples() {
+        for (file, prefix) in [("README.md", ""), ("src/meta.rs", "//!")] {
+            let text = fs::read_to_string(file).unwrap();
+            let meta = Meta::parse(file, &example(&text, prefix)).unwrap();
+            assert!(meta.rules.ignore_query_stack, "{file}");
+            for other in meta.same_root_cause.iter().chain(&meta.known_ambiguous) {
+                assert!(
+                    Path::new("ice").join(format!("{other}.rs")).exists(),
+                    "the example in {file} refers to {other}, which isn't in the database"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_meta_parse() {
+        let meta = Meta::parse(
+            "ice/1.toml",
+            "known-ambiguous = [\"2\"]\n[match]\nmessage = \"^broken MIR\"\n",
+        )
+        .unwrap();
+        assert_eq!(meta.known_ambiguous, ["2"]);
+        assert!(!meta.rules.ignore_query_stack);
+        assert!(meta.rules.message.unwrap().is_match("broken MIR in foo"));
+        assert!(Meta::parse("ice/1.toml", "known-ambigous = []").is_err());
+        assert!(Meta::parse("ice/1.toml", "status = \"fixed\"").is_err());
+        assert!(Meta::parse("ice/1.toml", "[match]\nmessage = \"(\"").is_err());
+    }
+
+    #[test]
+    fn test_declared() {
+        let meta = Meta::parse("ice/1.toml", "known-ambiguous = [\"2\"]").unwrap();
+        assert_eq!(
+            declared(&meta, "ice/1.rs", "ice/2.rs"),
+            Some(Relation::KnownAmbiguous)
+        );
+        assert_eq!(declared(&meta, "ice/1.rs", "private/2.rs"), None);
+        assert_eq!(declared(&meta, "ice/1.rs", "ice/3.rs"), None);
+    }
+
+    #[test]
+    fn test_meta_describe() {
+        let meta = Meta::parse(
+            "ice/1.toml",
+            "title = \"ICE\"\nstatus = \"open\"\nfeatures = [\"gce\"]\nadded = \"2025-06-01\"\n",
+        )
+        .unwrap();
+        assert_eq!(meta.issue("ice/123-2.rs"), Some(123));
+        assert_eq!(
+            meta.known_bug("ice/123-2.rs").as_deref(),
+            Some("//@ known-bug: #123")
+        );
+        assert_eq!(
+            meta.describe("ice/123.rs"),
+            [
+                "issue: rust-lang/rust#123 (open): ICE",
+                "requires: feature(gce)",
+                "added 2025-06-01"
+            ]
+        );
+        assert_eq!(Meta::default().issue("ice/project-simd.rs"), None);
+    }
+}
@@ -0,0 +1,296 @@
+//! Normalization of ICE messages, so that messages of the same ICE in
+//! different programs compare equal.
+//!
+//! Messages embed names from the program (`DefId(0:12 ~ drice[abcd]::foo)`,
+//! type names, generic parameters), spans, inference variables and numbers,
+//! which are replaced with placeholders.
+
+use std::collections::BTreeSet;
+
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// The end of the word (identifier or number) starting at `start`
+fn word_end(chars: &[char], start: usize) -> usize {
+    chars[start..]
+        .iter()
+        .position(|&c| !is_ident_char(c))
+        .map_or(chars.len(), |n| start + n)
+}
+
+/// The end of the crate hash like `[e8a5]` starting at `start`, if any
+fn crate_hash_end(chars: &[char], start: usize) -> Option<usize> {
+    if chars.get(start) != Some(&'[') {
+        return None;
+    }
+    let len = chars[start + 1..]
+        .iter()
+        .take_while(|c| c.is_ascii_hexdigit())
+        .count();
+    let end = start + 1 + len;
+    (len >= 4 && chars.get(end) == Some(&']')).then_some(end + 1)
+}
+
+/// Names from the program: crates with a hash, like `drice[e8a5]`, and the
+/// segments of paths in them, like `foo` and `Bar` in `drice[e8a5]::foo::Bar`
+fn program_names(chars: &[char]) -> BTreeSet<String> {
+    let mut names = BTreeSet::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let Some(end) = crate_hash_end(chars, i) else {
+            i += 1;
+            continue;
+        };
+        let krate_start = chars[..i]
+            .iter()
+            .rposition(|&c| !is_ident_char(c))
+            .map_or(0, |n| n + 1);
+        if krate_start < i {
+            names.insert(chars[krate_start..i].iter().collect());
+        }
+        i = end;
+        let path_len = chars[i..]
+            .iter()
+            .take_while(|&&c| is_ident_char(c) || "':{}#".contains(c))
+            .count();
+        let path: String = chars[i..i + path_len].iter().collect();
+        for segment in path.split("::") {
+            let segment = segment.trim_start_matches('\'');
+            if segment.starts_with(|c: char| c.is_alphabetic())
+                && segment.chars().all(is_ident_char)
+            {
+                names.insert(segment.to_owned());
+            }
+        }
+        i += path_len;
+    }
+    names
+}
+
+/// The end of the span like `src/main.rs:1:2: 1:5 (#0)` whose file name
+/// ends at `start`, if any
+fn span_end(chars: &[char], start: usize) -> Option<usize> {
+    /// The end of `L:C` starting at `i`
+    fn line_col(chars: &[char], i: usize) -> Option<usize> {
+        let digits = |i: usize| chars[i..].iter().take_while(|c| c.is_ascii_digit()).count();
+        let line = digits(i);
+        if line == 0 || chars.get(i + line) != Some(&':') {
+            return None;
+        }
+        let col = digits(i + line + 1);
+        (col > 0).then_some(i + line + 1 + col)
+    }
+    if chars.get(start) != Some(&':') {
+        return None;
+    }
+    let mut end = line_col(chars, start + 1)?;
+    if chars[end..].starts_with(&[':', ' '])
+        && let Some(e) = line_col(chars, end + 2)
+    {
+        end = e;
+    }
+    if chars[end..].starts_with(&[' ', '(', '#'])
+        && let Some(n) = chars[end + 3..].iter().position(|&c| c == ')')
+        && chars[end + 3..end + 3 + n].iter().all(char::is_ascii_digit)
+    {
+        end += 4 + n;
+    }
+    Some(end)
+}
+
+/// Replace the program-specific parts of an ICE message with placeholders:
+/// `DefId(_)`, `<span>`, `` `_` `` for anything between backticks, `_` for
+/// names from the program and generic parameters, and `N` for numbers
+#[must_use]
+pub(crate) fn message(msg: &str) -> String {
+    let chars: Vec<char> = msg.chars().collect();
+    let names = program_names(&chars);
+    let mut out = String::with_capacity(msg.len());
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if chars[i..].starts_with(&['D', 'e', 'f', 'I', 'd', '(']) {
+            // Skip to the matching parenthesis
+            let mut depth = 0;
+            let mut j = i + 5;
+            while j < chars.len() {
+                match chars[j] {
+                    '(' => depth += 1,
+                    ')' => depth -= 1,
+                    _ => {}
+                }
+                j += 1;
+                if depth == 0 {
+                    break;
+                }
+            }
+            out.push_str("DefId(_)");
+            i = j;
+        } else if c == '`'
+            && let Some(n) = chars[i + 1..].iter().position(|&c| c == '`')
+        {
+            out.push_str("`_`");
+            i += n + 2;
+        } else if let Some(end) = crate_hash_end(&chars, i) {
+            out.push_str("[_]");
+            i = end;
+        } else if is_ident_char(c) {
+            let end = word_end(&chars, i);
+            let word: String = chars[i..end].iter().collect();
+            // File names of spans, like `main.rs` in `src/main.rs:1:2`
+            let file_end = if chars[end..].starts_with(&['.', 'r', 's']) {
+                end + 3
+            } else {
+                end
+            };
+            if let Some(span) = span_end(&chars, file_end) {
+                // Drop the directories, which were already pushed
+                let dir_len = out
+                    .chars()
+                    .rev()
+                    .take_while(|&c| !c.is_whitespace() && !"([`".contains(c))
+                    .map(char::len_utf8)
+                    .sum::<usize>();
+                out.truncate(out.len() - dir_len);
+                out.push_str("<span>");
+                i = span;
+                continue;
+            }
+            if word.starts_with("0x") {
+                out.push('N');
+            } else if c.is_ascii_digit() {
+                // Keep suffixes, like `t` in `?0t` or `_u32` in `1_u32`
+                out.push('N');
+                out.push_str(word.trim_start_matches(|c: char| c.is_ascii_digit()));
+            } else if names.contains(&word) || chars[end..].starts_with(&['/', '#']) {
+                out.push('_');
+            } else {
+                out.push_str(&word);
+            }
+            i = end;
+        } else {
+            out.push(c);
+            i += 1;
+        }
+    }
+    out
+}
+
+/// The shape of a (normalized) message: its words outside brackets, with the
+/// contents of brackets elided, like `Binder {..} has parameters`.
+///
+/// Besides names, messages embed types from the program, which can't be told
+/// apart from types of `rustc`.
+#[must_use]
+pub(crate) fn shape(msg: &str) -> String {
+    let mut out = String::with_capacity(msg.len());
+    let mut depth = 0_usize;
+    let mut prev = ' ';
+    let mut chars = msg.chars().peekable();
+    while let Some(c) = chars.next() {
+        // Not the arrows in `fn() -> T` and `A <- B`
+        let arrow = (c == '>' && prev == '-') || (c == '<' && chars.peek() == Some(&'-'));
+        match c {
+            '(' | '[' | '{' | '<' if !arrow => {
+                if depth == 0 {
+                    out.push(c);
+                }
+                depth += 1;
+            }
+            ')' | ']' | '}' | '>' if !arrow && depth > 0 => {
+                depth -= 1;
+                if depth == 0 {
+                    if !matches!(prev, '(' | '[' | '{' | '<') {
+                        out.push_str("..");
+                    }
+                    out.push(c);
+                }
+            }
+            _ if depth > 0 => {}
+            _ if c.is_whitespace() => {
+                if !out.ends_with(' ') {
+                    out.push(' ');
+                }
+            }
+            _ => out.push(c),
+        }
+        prev = c;
+    }
+    out.trim_end().to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{message, shape};
+
+    #[test]
+    fn test_message() {
+        assert_eq!(
+            message(
+                "impl DefId(0:11 ~ drice[e8a5]::{impl#1}) was matchable against Binder { value: TraitPredicate(<((S,),) as SimpleTrait>, polarity:Positive), bound_vars: [] } but now is not"
+            ),
+            "impl DefId(_) was matchable against Binder { value: TraitPredicate(<((S,),) as SimpleTrait>, polarity:Positive), bound_vars: [] } but now is not"
+        );
+        assert_eq!(
+            message(
+                "can't project out of Alias(AliasTy { args: [], kind: Opaque { def_id: DefId(0:7 ~ drice[e8a5]::mk::{opaque#0}) }, .. })"
+            ),
+            "can't project out of Alias(AliasTy { args: [], kind: Opaque { def_id: DefId(_) }, .. })"
+        );
+        assert_eq!(
+            message("index out of bounds: the len is 2 but the index is 0x2"),
+            "index out of bounds: the len is N but the index is N"
+        );
+        assert_eq!(
+            message("type parameter `S/#1` (S/#1/1) out of range when instantiating, args=[A/#0]"),
+            "type parameter `_` (_/#N/N) out of range when instantiating, args=[_/#N]"
+        );
+        assert_eq!(
+            message(
+                "node_type: no type for node HirId(DefId(0:5 ~ drice[e8a5]::bar).3) (type `T`)"
+            ),
+            "node_type: no type for node HirId(DefId(_).N) (type `_`)"
+        );
+        assert_eq!(
+            message("cause: ObligationCause { span: ice/136661.rs:23:1: 23:36 (#0), body_id: x }"),
+            "cause: ObligationCause { span: <span>, body_id: x }"
+        );
+        assert_eq!(
+            message("unexpected infer type: FreshTy(0) <- '?3 ?0t"),
+            "unexpected infer type: FreshTy(N) <- '?N ?Nt"
+        );
+    }
+
+    #[test]
+    fn test_message_program_names() {
+        // `Foo` is only known to be from the program from the path
+        assert_eq!(
+            message(
+                "cannot relate <Foo as Trait> and DefId(0:6 ~ foo_bar[1a2b]::Foo), see foo_bar[1a2b]::Trait"
+            ),
+            "cannot relate <_ as _> and DefId(_), see _[_]::_"
+        );
+    }
+
+    #[test]
+    fn test_shape() {
+        assert_eq!(
+            shape(
+                "impl DefId(_) was matchable against Binder { value: <&[u8] as Archive> } but now is not"
+            ),
+            "impl DefId(..) was matchable against Binder {..} but now is not"
+        );
+        assert_eq!(
+            shape("failed to resolve instance for <() as Supertrait<()>>::method"),
+            "failed to resolve instance for <..>::method"
+        );
+        assert_eq!(
+            shape(
+                "broken MIR in Item(DefId(_)) at bbN[N]:\n    Failed (Binder { value: fn(&()) -> T, bound_vars: [] } <- T)"
+            ),
+            "broken MIR in Item(..) at bbN[..]: Failed (..)"
+        );
+        assert_eq!(shape("args: [] and ()"), "args: [] and ()");
+    }
+}
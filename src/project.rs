@@ -0,0 +1,169 @@
+//! Reproducers that span several files.
+//!
+//! Besides a single `.rs` file, a reproducer can be a directory with either a
+//! Cargo project, or a single `.rs` file whose auxiliary crates (see
+//! [`crate::directive::Aux`]) are in `auxiliary/`, like in `rust-lang/rust`.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process,
+};
+
+use anyhow::Context;
+
+use crate::{
+    cache::Cache,
+    rustc::{self, RustcConfig, Toolchain},
+};
+
+/// Whether `path` is a reproducer to run `rustc` on, rather than its output
+#[must_use]
+pub(crate) fn is_reproducer(path: &Path) -> bool {
+    path.is_dir() || path.extension().and_then(|s| s.to_str()) == Some("rs")
+}
+
+#[must_use]
+pub(crate) fn is_cargo(path: &Path) -> bool {
+    path.join("Cargo.toml").is_file()
+}
+
+/// Whether the directory `dir` is a reproducer: a Cargo project, or else has
+/// a single `.rs` file (see [`main_file`])
+#[must_use]
+pub(crate) fn is_project(dir: &Path) -> bool {
+    is_cargo(dir) || main_file(dir).is_ok()
+}
+
+/// The single `.rs` file in `dir`
+pub(crate) fn main_file(dir: &Path) -> anyhow::Result<PathBuf> {
+    let mut files = Vec::new();
+    for entry in
+        fs::read_dir(dir).with_context(|| format!("failed to read directory: {}", dir.display()))?
+    {
+        let path = entry
+            .with_context(|| format!("failed to read entry in directory: {}", dir.display()))?
+            .path();
+        if path.is_file() && path.extension().and_then(|s| s.to_str()) == Some("rs") {
+            files.push(path);
+        }
+    }
+    match <[_; 1]>::try_from(files) {
+        Ok([file]) => Ok(file),
+        Err(files) => anyhow::bail!(
+            "{}: expected a Cargo.toml or exactly one .rs file, found {} .rs files",
+            dir.display(),
+            files.len()
+        ),
+    }
+}
+
+/// Append the files under `dir` to `out`, skipping build outputs
+fn collect_sources(root: &Path, dir: &Path, out: &mut String) -> anyhow::Result<()> {
+    let mut paths = Vec::new();
+    for entry in
+        fs::read_dir(dir).with_context(|| format!("failed to read directory: {}", dir.display()))?
+    {
+        let path = entry
+            .with_context(|| format!("failed to read entry in directory: {}", dir.display()))?
+            .path();
+        let hidden = path
+            .file_name()
+            .and_then(|s| s.to_str())
+            .is_some_and(|name| name.starts_with('.') || name == "target");
+        if !hidden {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    for path in paths {
+        if path.is_dir() {
+            collect_sources(root, &path, out)?;
+            continue;
+        }
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("failed to read file: {}", path.display()))?;
+        out.push_str(&path.strip_prefix(root).unwrap_or(&path).to_string_lossy());
+        out.push('\n');
+        out.push_str(&content);
+    }
+    Ok(())
+}
+
+/// The source code of a reproducer, for looking for internal features and
+/// cache keys
+pub(crate) fn sources(path: &Path) -> anyhow::Result<String> {
+    if !path.is_dir() {
+        return fs::read_to_string(path)
+            .with_context(|| format!("failed to read file: {}", path.display()));
+    }
+    let mut out = String::new();
+    collect_sources(path, path, &mut out)?;
+    Ok(out)
+}
+
+/// The crate named in `error: could not compile `foo` (lib)`
+fn failed_crate(stderr: &str) -> Option<String> {
+    stderr.lines().find_map(|line| {
+        let rest = line.strip_prefix("error: could not compile `")?;
+        Some(rest[..rest.find('`')?].to_owned())
+    })
+}
+
+/// Build the Cargo project in `dir` with `cargo build`
+pub(crate) fn cargo(config: &RustcConfig, dir: &Path) -> anyhow::Result<rustc::Output> {
+    let args = [String::from("build")];
+    rustc::cached(config, &Cache::key(&sources(dir)?, &args), || {
+        let target_dir = tempfile::tempdir().context("failed to create temporary directory")?;
+        let mut cmd = process::Command::new("cargo");
+        match &config.toolchain {
+            Toolchain::Rustup(name) => {
+                cmd.arg(format!("+{name}"));
+            }
+            Toolchain::Path(path) => {
+                cmd.env("RUSTC", path);
+            }
+        }
+        // One job at a time, so that output of rustc isn't interleaved
+        cmd.args(&args)
+            .args(["--jobs=1", "--color=never", "--manifest-path"])
+            .arg(dir.join("Cargo.toml"))
+            .arg("--target-dir")
+            .arg(target_dir.path());
+        let mut output = rustc::execute(config, cmd, dir)?;
+        output.krate = failed_crate(&output.stderr);
+        Ok(output)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{failed_crate, is_project};
+
+    #[test]
+    fn test_is_project() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |file: &str| dir.path().join(file);
+        fs::create_dir_all(path("notes")).unwrap();
+        fs::write(path("notes/todo.md"), "").unwrap();
+        assert!(!is_project(&path("notes")));
+        fs::create_dir_all(path("single/auxiliary")).unwrap();
+        fs::write(path("single/main.rs"), "").unwrap();
+        fs::write(path("single/auxiliary/dep.rs"), "").unwrap();
+        assert!(is_project(&path("single")));
+        fs::write(path("single/other.rs"), "").unwrap();
+        assert!(!is_project(&path("single")));
+        fs::create_dir_all(path("cargo/src")).unwrap();
+        fs::write(path("cargo/Cargo.toml"), "").unwrap();
+        assert!(is_project(&path("cargo")));
+    }
+
+    #[test]
+    fn test_failed_crate() {
+        let stderr = "   Compiling dep v0.1.0 (/tmp/dep)\nerror: internal compiler error: boom\n\nerror: could not compile `dep` (lib)\n";
+        assert_eq!(failed_crate(stderr).as_deref(), Some("dep"));
+        assert_eq!(failed_crate("   Compiling dep v0.1.0\n"), None);
+    }
+}
@@ -0,0 +1,87 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use tracing::{debug, info};
+
+use crate::{check, db, par, rustc};
+
+pub(crate) struct RebuildConfig {
+    pub rustc: rustc::RustcConfig,
+    pub jobs: usize,
+    pub retire: bool,
+}
+
+/// Run rustc on `rs` and save its output, returning whether it ICEd.
+///
+/// If it no longer ICEs, the last ICE is kept (if any), so that it can still
+/// be matched once the entry is retired, see [`crate::database::retired`].
+fn rebuild_one(config: &rustc::RustcConfig, rs: &Path) -> anyhow::Result<bool> {
+    debug!("Processing {}", rs.display());
+    let output = rustc::go(config, rs)
+        .with_context(|| format!("failed to run rustc on file: {}", rs.display()))?;
+    let ices = output.exit != rustc::Exit::Timeout && check::is_ice(&output.text()).is_some();
+    if ices || !rs.with_extension("out").exists() {
+        db::save_output(rs, &output)?;
+    }
+    Ok(ices)
+}
+
+pub(crate) fn rebuild(config: RebuildConfig) -> anyhow::Result<()> {
+    let mut files = Vec::new();
+    for dir in [db::ICE_DIR, db::DUP_DIR] {
+        let dir = Path::new(dir);
+        for name in db::names(dir)? {
+            files.push(dir.join(format!("{name}.rs")));
+        }
+    }
+
+    let bar = indicatif::ProgressBar::new(
+        u64::try_from(files.len())
+            .with_context(|| format!("files length {} exceeds u64::MAX", files.len()))?,
+    );
+    let mut fixed: Vec<PathBuf> = Vec::new();
+    // Outputs are saved, so they have to be of these files
+    let rustc = config.rustc.uncached();
+    par::for_each_ordered(
+        &files,
+        config.jobs,
+        |rs| {
+            let result = rebuild_one(&rustc, rs);
+            bar.inc(1);
+            result
+        },
+        |rs, result| {
+            if !result? {
+                fixed.push(rs.clone());
+            }
+            Ok(())
+        },
+    )?;
+    bar.finish_and_clear();
+
+    for rs in &fixed {
+        println!("No ICE: {}", rs.display());
+    }
+    if !config.retire {
+        return Ok(());
+    }
+
+    // Only retire entries where the original reproducer stopped ICEing
+    let mut retired = false;
+    for rs in &fixed {
+        if rs.parent() != Some(Path::new(db::ICE_DIR)) {
+            continue;
+        }
+        let name = rs
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .with_context(|| format!("failed to get file name from path: {}", rs.display()))?;
+        info!("retiring {name}");
+        db::retire(name, false)?;
+        retired = true;
+    }
+    if retired {
+        db::reindex(false)?;
+    }
+    Ok(())
+}
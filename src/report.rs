@@ -0,0 +1,838 @@
+//! Structured view of the output of `rustc` for an ICE.
+//!
+//! [`IceReport::parse`] understands stderr of `rustc` (including output of
+//! `cargo build`) and `rustc-ice-*.txt` files, and both concatenated.
+
+use std::fmt;
+
+use crate::{normalize, rustc::ICE_DUMP_HEADER};
+
+/// How `rustc` crashed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IceKind {
+    /// A panic, e.g., a failed assertion or an `unwrap`
+    Panic,
+    /// `bug!` or `span_bug!`, reported as `error: internal compiler error`
+    Bug,
+    /// A delayed bug, reported when compilation finishes without errors
+    DelayedBug,
+    Segfault,
+}
+
+impl IceKind {
+    /// The kind of ICE in `text`, or `None` if it isn't an ICE
+    #[must_use]
+    pub fn detect(text: &str) -> Option<Self> {
+        if text.contains("error: rustc interrupted by SIGSEGV, printing backtrace") {
+            return Some(Self::Segfault);
+        }
+        let is_ice = text.contains("error: internal compiler error:")
+            || text.contains("error: the compiler unexpectedly panicked")
+            // rustc-ice-*.txt files
+            || text.starts_with("thread 'rustc' panicked at ")
+            || text.starts_with("delayed bug: ");
+        if !is_ice {
+            None
+        } else if text.contains("note: delayed at ")
+            || text.starts_with("delayed bug: ")
+            || text.contains("note: no errors encountered even though delayed bugs were created")
+        {
+            Some(Self::DelayedBug)
+        } else if text.contains("error: internal compiler error:") {
+            Some(Self::Bug)
+        } else {
+            Some(Self::Panic)
+        }
+    }
+}
+
+/// A location in the source of `rustc`, like `compiler/rustc_middle/src/ty/fold.rs:237:33`
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct Location {
+    pub file: String,
+    pub line: u32,
+    pub column: u32,
+}
+
+impl Location {
+    #[must_use]
+    pub fn parse(s: &str) -> Option<Self> {
+        let mut parts = s.rsplitn(3, ':');
+        let column = parts.next()?.parse().ok()?;
+        let line = parts.next()?.parse().ok()?;
+        let file = parts.next().filter(|f| !f.is_empty())?;
+        Some(Self {
+            file: file.to_owned(),
+            line,
+            column,
+        })
+    }
+
+    /// The path in `rust-lang/rust`, without the prefix that differs between
+    /// builds, like `/rustc-dev/<commit hash>/`
+    #[must_use]
+    pub fn source_path(&self) -> &str {
+        if self.file.starts_with("compiler/") || self.file.starts_with("library/") {
+            return &self.file;
+        }
+        ["/compiler/", "/library/"]
+            .iter()
+            .find_map(|dir| self.file.find(dir))
+            .map_or(&self.file, |idx| &self.file[idx + 1..])
+    }
+
+    /// The crate, like `rustc_middle` for `compiler/rustc_middle/src/ty/fold.rs`
+    #[must_use]
+    pub fn crate_name(&self) -> Option<&str> {
+        let mut parts = self.source_path().split('/');
+        match (parts.next()?, parts.next()?, parts.next()?) {
+            ("compiler" | "library", name, "src") => Some(name),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.column)
+    }
+}
+
+/// A frame of the query stack, like `#0 [typeck] type-checking `main``
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct QueryFrame {
+    pub query: String,
+    pub description: String,
+}
+
+impl QueryFrame {
+    fn parse(line: &str) -> Option<Self> {
+        let rest = line.trim().strip_prefix('#')?;
+        let (_index, rest) = rest.split_once(" [")?;
+        let (query, description) = rest.split_once("] ")?;
+        Some(Self {
+            query: query.to_owned(),
+            description: description.to_owned(),
+        })
+    }
+}
+
+/// A frame of a backtrace, like `12: rustc_middle::util::bug::bug_fmt`
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct BacktraceFrame {
+    pub index: usize,
+    /// The symbol, without the address that `rustc-ice-*.txt` files include
+    pub symbol: String,
+    /// From the `at` line below the symbol (with `RUST_BACKTRACE=full`)
+    pub location: Option<Location>,
+}
+
+impl BacktraceFrame {
+    fn parse(line: &str) -> Option<Self> {
+        let (index, symbol) = line.trim().split_once(": ")?;
+        let index = index.parse().ok()?;
+        // `0x7f19144772cb - symbol` in rustc-ice-*.txt files
+        let symbol = match symbol.trim_start().split_once(" - ") {
+            Some((address, symbol)) if address.starts_with("0x") => symbol,
+            _ => symbol,
+        };
+        Some(Self {
+            index,
+            symbol: symbol.to_owned(),
+            location: None,
+        })
+    }
+
+    /// A frame like `/path/to/librustc_driver-abcd.so(+0x3c8e78c)[0x7f40d0a8e78c]`
+    /// from the backtrace that `rustc` prints on a segfault. Without debug info,
+    /// the symbol is the file name and the offset, e.g.
+    /// `librustc_driver-abcd.so+0x3c8e78c`.
+    fn parse_segfault(index: usize, line: &str) -> Option<Self> {
+        let (path, rest) = line.trim().split_once('(')?;
+        let (offset, _address) = rest.split_once(")[")?;
+        let file = path.rsplit('/').next().unwrap_or(path);
+        Some(Self {
+            index,
+            symbol: format!("{file}{offset}"),
+            location: None,
+        })
+    }
+
+    /// The symbol without generic arguments, the crate hashes of
+    /// `rustc-ice-*.txt` files and suffixes like `.cold` from LLVM, e.g.
+    /// `rustc_middle::util::bug::bug_fmt`
+    #[must_use]
+    pub fn function(&self) -> String {
+        let mut out = String::with_capacity(self.symbol.len());
+        let mut rest = self.symbol.as_str();
+        if let Some(idx) = rest.find(".cold").or_else(|| rest.find(".llvm.")) {
+            rest = &rest[..idx];
+        }
+        while let Some(c) = rest.chars().next() {
+            if let Some(args) = rest.strip_prefix("::<") {
+                rest = skip_generic_args(args);
+            } else if let Some(hash) = rest.strip_prefix('[')
+                && let Some(end) = hash.find(']')
+                && hash[..end].chars().all(|c| c.is_ascii_hexdigit())
+            {
+                rest = &hash[end + 1..];
+            } else {
+                out.push(c);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+        out
+    }
+}
+
+/// Skip to the end of generic arguments, just after the opening `<`
+fn skip_generic_args(s: &str) -> &str {
+    let mut depth = 1;
+    let mut prev = ' ';
+    for (i, c) in s.char_indices() {
+        match c {
+            '<' => depth += 1,
+            // Not `->` in `fn() -> T`
+            '>' if prev != '-' => {
+                depth -= 1;
+                if depth == 0 {
+                    return &s[i + 1..];
+                }
+            }
+            _ => {}
+        }
+        prev = c;
+    }
+    ""
+}
+
+/// Frames in the machinery of panics and diagnostics, rather than where the
+/// ICE originated
+const MACHINERY: &[&str] = &[
+    "__rustc::",
+    "std::panicking::",
+    "core::panicking::",
+    "std::panic::",
+    "std::sys::backtrace::",
+    "std::backtrace::",
+    "core::option::",
+    "core::result::",
+    "rustc_errors::",
+    "rustc_middle::util::bug::",
+    "rustc_middle::ty::context::tls::",
+    "rustc_driver_impl::",
+];
+
+/// Frames in [`IceReport::signature`]
+const SIGNATURE_FRAMES: usize = 5;
+
+/// A delayed bug, from `note: delayed at ...` and the backtrace after it
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct DelayedBug {
+    pub location: Option<Location>,
+    pub backtrace: Vec<BacktraceFrame>,
+}
+
+/// A diagnostic emitted before the ICE, like `error[E0425]: cannot find value`
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct Diagnostic {
+    /// `error` or `warning`
+    pub level: String,
+    pub code: Option<String>,
+    pub message: String,
+}
+
+impl Diagnostic {
+    fn parse(line: &str) -> Option<Self> {
+        let (head, message) = line.split_once(": ")?;
+        let (level, code) = match head.split_once('[') {
+            Some((level, code)) => (level, Some(code.strip_suffix(']')?.to_owned())),
+            None => (head, None),
+        };
+        if level != "error" && level != "warning" {
+            return None;
+        }
+        // Summaries like `warning: 1 warning emitted`
+        if message.starts_with("aborting due to") || message.ends_with(" emitted") {
+            return None;
+        }
+        Some(Self {
+            level: level.to_owned(),
+            code,
+            message: message.to_owned(),
+        })
+    }
+}
+
+/// An ICE, as reported by `rustc`
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct IceReport {
+    pub kind: IceKind,
+    /// Where the ICE originated: the location of the panic, or where the
+    /// first delayed bug was delayed
+    pub location: Option<Location>,
+    /// The message of the `bug!` or the panic
+    pub message: Option<String>,
+    /// `None` if `rustc` didn't print the query stack
+    pub query_stack: Option<Vec<QueryFrame>>,
+    pub backtrace: Vec<BacktraceFrame>,
+    pub delayed: Vec<DelayedBug>,
+    /// Errors and warnings before the ICE
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// Whether `line` starts the report of an ICE
+fn starts_ice(line: &str) -> bool {
+    (line.starts_with("thread 'rustc'") && line.contains(" panicked at "))
+        || line.starts_with("error: internal compiler error: ")
+        // rustc-ice-*.txt files of delayed bugs
+        || line.starts_with("delayed bug: ")
+        || line.starts_with("note: no errors encountered even though delayed bugs were created")
+        || line.starts_with("error: rustc interrupted by SIGSEGV")
+}
+
+/// Whether `line` follows a (multi-line) message
+fn ends_message(line: &str) -> bool {
+    // Source snippets like ` --> file.rs:1:1`, `14 |     x` and `  = note: ...`
+    let snippet = line
+        .trim_start()
+        .trim_start_matches(|c: char| c.is_ascii_digit())
+        .trim_start();
+    line.trim().is_empty()
+        || line == "stack backtrace:"
+        || line.starts_with("note: ")
+        || line.starts_with("error")
+        || line.starts_with("thread '")
+        || ["-->", ":::", "|", "= "]
+            .iter()
+            .any(|prefix| snippet.starts_with(prefix))
+}
+
+/// Split the output of `rustc` into the text of each ICE in it, each with the
+/// diagnostics before the first ICE.
+///
+/// A `bug!` is reported as `error: internal compiler error` followed by a panic,
+/// which belong together. The ICE dump (see [`ICE_DUMP_HEADER`]) has the full
+/// backtrace of the first ICE, so it goes with it.
+fn split_occurrences(text: &str) -> Vec<String> {
+    let (stderr, dump) = match text.split_once(ICE_DUMP_HEADER) {
+        Some((stderr, dump)) => (stderr, Some(dump)),
+        None => (text, None),
+    };
+    let mut preamble = String::new();
+    let mut occurrences: Vec<String> = Vec::new();
+    // Whether the last occurrence has a panic, and an `error: internal compiler error`
+    let (mut panic, mut bug) = (false, false);
+    for line in stderr.lines() {
+        let is_panic = line.starts_with("thread 'rustc'") && line.contains(" panicked at ");
+        let is_bug = line.starts_with("error: internal compiler error: ")
+            || line.starts_with("delayed bug: ");
+        let new = if is_panic {
+            panic || !bug
+        } else {
+            starts_ice(line) && (panic || bug || occurrences.is_empty())
+        };
+        if new {
+            occurrences.push(preamble.clone());
+            (panic, bug) = (false, false);
+        }
+        panic |= is_panic;
+        bug |= is_bug;
+        let out = occurrences.last_mut().unwrap_or(&mut preamble);
+        out.push_str(line);
+        out.push('\n');
+    }
+    if let Some(dump) = dump {
+        match occurrences.first_mut() {
+            Some(first) => {
+                first.push_str(ICE_DUMP_HEADER);
+                first.push_str(dump);
+            }
+            // E.g., stderr of `cargo build` without the ICE
+            None => occurrences.push(text.to_owned()),
+        }
+    }
+    if occurrences.is_empty() {
+        occurrences.push(preamble);
+    }
+    occurrences
+}
+
+/// Parse the backtrace frames at the start of `lines`
+fn parse_backtrace(lines: &mut std::iter::Peekable<std::str::Lines<'_>>) -> Vec<BacktraceFrame> {
+    let mut frames: Vec<BacktraceFrame> = Vec::new();
+    while let Some(line) = lines.peek() {
+        let trimmed = line.trim();
+        if let Some(frame) = BacktraceFrame::parse(line) {
+            frames.push(frame);
+        } else if let Some(at) = trimmed.strip_prefix("at ") {
+            if let Some(frame) = frames.last_mut() {
+                frame.location = Location::parse(at);
+            }
+        } else if !(trimmed.starts_with("[... omitted ") && trimmed.ends_with(" ...]")) {
+            break;
+        }
+        lines.next();
+    }
+    frames
+}
+
+/// Parse the backtrace after `error: rustc interrupted by SIGSEGV`, from the
+/// frame that raised the signal
+fn parse_segfault_backtrace(
+    lines: &mut std::iter::Peekable<std::str::Lines<'_>>,
+) -> Vec<BacktraceFrame> {
+    let mut frames: Vec<BacktraceFrame> = Vec::new();
+    while let Some(line) = lines.peek() {
+        let trimmed = line.trim();
+        if let Some(frame) = BacktraceFrame::parse_segfault(frames.len(), trimmed) {
+            frames.push(frame);
+        } else if !(trimmed.is_empty() || trimmed.starts_with("### ")) {
+            break;
+        }
+        lines.next();
+    }
+    // Skip the signal handler and the trampoline in libc
+    if let Some(idx) = frames.iter().position(|f| f.symbol.starts_with("libc.so")) {
+        frames.drain(..=idx);
+    }
+    frames
+}
+
+/// The message of `error: internal compiler error: <path>: message`
+fn bug_message(line: &str) -> &str {
+    let first = &line[..line.find(' ').unwrap_or(line.len())];
+    if first.contains('/') && first.contains(':') {
+        return line[first.len()..].trim_start();
+    }
+    line
+}
+
+impl IceReport {
+    /// Parse the output of `rustc`, or `None` if it isn't an ICE
+    #[must_use]
+    pub fn parse(text: &str) -> Option<Self> {
+        let mut report = Self {
+            kind: IceKind::detect(text)?,
+            location: None,
+            message: None,
+            query_stack: None,
+            backtrace: Vec::new(),
+            delayed: Vec::new(),
+            diagnostics: Vec::new(),
+        };
+        let mut in_ice = false;
+        let mut lines = text.lines().peekable();
+        while let Some(line) = lines.next() {
+            in_ice |= starts_ice(line);
+            if !in_ice {
+                report.diagnostics.extend(Diagnostic::parse(line));
+            }
+            if line.starts_with("thread 'rustc'")
+                && let Some(idx) = line.find("panicked at ")
+            {
+                let at = line[idx + "panicked at ".len()..].trim_end_matches(':');
+                if report.location.is_none() {
+                    report.location = Location::parse(at);
+                }
+                let mut message = Vec::new();
+                while let Some(next) = lines.next_if(|l| !ends_message(l)) {
+                    message.push(next);
+                }
+                // The payload of `bug!`, the actual message isn't in rustc-ice-*.txt
+                if report.message.is_none() && !message.is_empty() && message != ["Box<dyn Any>"] {
+                    report.message = Some(message.join("\n"));
+                }
+            } else if let Some(msg) = line.strip_prefix("error: internal compiler error: ") {
+                let mut message = vec![bug_message(msg)];
+                while let Some(next) = lines.next_if(|l| !ends_message(l)) {
+                    message.push(next);
+                }
+                if report.message.is_none() {
+                    report.message = Some(message.join("\n"));
+                }
+            } else if let Some(msg) = line.strip_prefix("delayed bug: ") {
+                // In rustc-ice-*.txt files, without a location
+                if report.message.is_none() {
+                    report.message = Some(msg.to_owned());
+                }
+                let backtrace = parse_backtrace(&mut lines);
+                report.delayed.push(DelayedBug {
+                    location: None,
+                    backtrace,
+                });
+            } else if let Some(idx) = line.find("note: delayed at ") {
+                let location =
+                    Location::parse(line[idx + "note: delayed at ".len()..].trim_end_matches(':'));
+                if report.location.is_none() {
+                    report.location.clone_from(&location);
+                }
+                let backtrace = parse_backtrace(&mut lines);
+                report.delayed.push(DelayedBug {
+                    location,
+                    backtrace,
+                });
+            } else if line.starts_with("error: rustc interrupted by SIGSEGV")
+                && report.backtrace.is_empty()
+            {
+                report.backtrace = parse_segfault_backtrace(&mut lines);
+            } else if line == "stack backtrace:" && report.backtrace.is_empty() {
+                report.backtrace = parse_backtrace(&mut lines);
+            } else if line.trim() == "query stack during panic:" && report.query_stack.is_none() {
+                let mut frames = Vec::new();
+                for line in lines.by_ref() {
+                    if line.trim() == "end of query stack" {
+                        break;
+                    }
+                    frames.extend(QueryFrame::parse(line));
+                }
+                report.query_stack = Some(frames);
+            }
+        }
+        Some(report)
+    }
+
+    /// Parse each ICE in the output of `rustc`, e.g., each delayed bug, or an
+    /// ICE and a panic while printing it.
+    ///
+    /// Unlike [`IceReport::parse`], which describes the output as a whole,
+    /// the kind, location, message, etc. of each one are its own.
+    #[must_use]
+    pub fn parse_all(text: &str) -> Vec<Self> {
+        let mut reports: Vec<Self> = split_occurrences(text)
+            .iter()
+            .filter_map(|occurrence| Self::parse(occurrence))
+            .collect();
+        // Delayed bugs are reported together, followed by one query stack
+        let stack = reports
+            .last()
+            .filter(|r| r.kind == IceKind::DelayedBug)
+            .and_then(|r| r.query_stack.clone());
+        for report in &mut reports {
+            if report.kind == IceKind::DelayedBug && report.query_stack.is_none() {
+                report.query_stack.clone_from(&stack);
+            }
+        }
+        reports
+    }
+
+    /// The message without names from the program, spans, numbers, etc., see
+    /// [`crate::normalize::message`]
+    #[must_use]
+    pub fn normalized_message(&self) -> Option<String> {
+        self.message.as_deref().map(normalize::message)
+    }
+
+    /// The first few of [`IceReport::functions`], which identify an ICE
+    /// without a query stack or location, e.g. a stack overflow.
+    ///
+    /// For segfaults, these are offsets in the libraries of `rustc`, which
+    /// only match between outputs of the same build.
+    #[must_use]
+    pub fn signature(&self) -> Vec<String> {
+        let mut functions = self.functions();
+        functions.truncate(SIGNATURE_FRAMES);
+        functions
+    }
+
+    /// The function in `rustc` where the ICE originated, followed by its
+    /// callers, judging by the backtrace (of the first delayed bug, if there
+    /// was no panic).
+    ///
+    /// Unlike [`IceReport::location`], this is stable across unrelated
+    /// changes to the source of `rustc`.
+    #[must_use]
+    pub fn functions(&self) -> Vec<String> {
+        let backtrace = match (self.backtrace.is_empty(), self.delayed.first()) {
+            (true, Some(delayed)) => &delayed.backtrace,
+            _ => &self.backtrace,
+        };
+        let functions = backtrace
+            .iter()
+            .map(BacktraceFrame::function)
+            .filter(|f| !MACHINERY.iter().any(|m| f.contains(m)))
+            .collect::<Vec<_>>();
+        // Skip to the crate of the location, as the frames of small functions
+        // may be inlined into their callers
+        let krate = self.location.as_ref().and_then(Location::crate_name);
+        let start = krate
+            .and_then(|krate| {
+                let prefix = format!("{krate}::");
+                functions.iter().position(|f| f.contains(&prefix))
+            })
+            .unwrap_or(0);
+        functions[start..].to_vec()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BacktraceFrame, IceKind, IceReport, Location, QueryFrame};
+
+    #[test]
+    fn test_parse_location() {
+        assert_eq!(Location::parse("foo"), None);
+        let location =
+            Location::parse("compiler/rustc_hir_analysis/src/hir_ty_lowering/dyn_trait.rs:425:17")
+                .unwrap();
+        assert_eq!(
+            location.file,
+            "compiler/rustc_hir_analysis/src/hir_ty_lowering/dyn_trait.rs"
+        );
+        assert_eq!((location.line, location.column), (425, 17));
+        let location =
+            Location::parse("/rustc-dev/e50aa6fba/compiler/rustc_middle/src/ty/fold.rs:237:33")
+                .unwrap();
+        assert_eq!(
+            location.source_path(),
+            "compiler/rustc_middle/src/ty/fold.rs"
+        );
+        assert_eq!(location.crate_name(), Some("rustc_middle"));
+    }
+
+    #[test]
+    fn test_function() {
+        let frame = |symbol: &str| BacktraceFrame {
+            index: 0,
+            symbol: symbol.to_owned(),
+            location: None,
+        };
+        assert_eq!(
+            frame("<rustc_middle[e3a9e155868aba9f]::ty::context::TyCtxt>::instantiate_bound_regions_uncached::<rustc_type_ir[1a2b]::ty_kind::FnSig<fn() -> u8>>::{closure#0}").function(),
+            "<rustc_middle::ty::context::TyCtxt>::instantiate_bound_regions_uncached::{closure#0}"
+        );
+        let report = IceReport::parse(
+            "thread 'rustc' panicked at /rustc-dev/abc/compiler/rustc_middle/src/ty/fold.rs:237:33:
+Box<dyn Any>
+stack backtrace:
+   0:     0x7f19132af1fb - std[d28b1718532fa52a]::panicking::begin_panic::<rustc_errors[5f6471fb26749c51]::ExplicitBug>
+   1:     0x7f19132af1fc - rustc_middle[e3a9e155868aba9f]::util::bug::bug_fmt
+   2:     0x7f19132af1fd - rustc_hir_typeck[e3a9e155868aba9e]::closure_sigs
+   3:     0x7f19132af1fe - <rustc_middle[e3a9e155868aba9f]::ty::fold::FnMutDelegate>::replace_ty
+",
+        )
+        .unwrap();
+        assert_eq!(
+            report.functions(),
+            ["<rustc_middle::ty::fold::FnMutDelegate>::replace_ty"]
+        );
+        assert_eq!(
+            frame("rustc_trait_selection::traits::const_evaluatable::is_const_evaluatable.cold")
+                .function(),
+            "rustc_trait_selection::traits::const_evaluatable::is_const_evaluatable"
+        );
+    }
+
+    #[test]
+    fn test_parse_panic() {
+        let report = IceReport::parse(
+            "error[E0425]: cannot find value `x` in this scope
+ --> ice/1.rs:1:13
+
+thread 'rustc' (843541) panicked at compiler/rustc_hir_analysis/src/collect.rs:425:17:
+assertion `left == right` failed
+  left: 1
+ right: 2
+stack backtrace:
+   0: __rustc::rust_begin_unwind
+   1: rustc_hir_analysis::collect::foo
+      [... omitted 1 frame ...]
+   2: rustc_interface::passes::analysis
+note: Some details are omitted, run with `RUST_BACKTRACE=full` for a verbose backtrace.
+
+error: the compiler unexpectedly panicked. This is a bug
+
+query stack during panic:
+#0 [type_of] computing type of `Foo::{constant#0}`
+#1 [analysis] running analysis passes on crate `drice`
+end of query stack
+",
+        )
+        .unwrap();
+        assert_eq!(report.kind, IceKind::Panic);
+        assert_eq!(
+            report.location.unwrap().to_string(),
+            "compiler/rustc_hir_analysis/src/collect.rs:425:17"
+        );
+        assert_eq!(
+            report.message.as_deref(),
+            Some("assertion `left == right` failed\n  left: 1\n right: 2")
+        );
+        assert_eq!(report.backtrace.len(), 3);
+        assert_eq!(
+            report.backtrace[1].symbol,
+            "rustc_hir_analysis::collect::foo"
+        );
+        assert_eq!(
+            report.query_stack.unwrap()[0],
+            QueryFrame {
+                query: String::from("type_of"),
+                description: String::from("computing type of `Foo::{constant#0}`"),
+            }
+        );
+        assert_eq!(report.diagnostics.len(), 1);
+        assert_eq!(report.diagnostics[0].code.as_deref(), Some("E0425"));
+    }
+
+    #[test]
+    fn test_parse_bug() {
+        let report = IceReport::parse(
+            "error: internal compiler error: /rustc-dev/abc/compiler/rustc_middle/src/ty/fold.rs:237:33: unexpected bound ty in binder: 0
+
+
+thread 'rustc' (14000) panicked at /rustc-dev/abc/compiler/rustc_middle/src/ty/fold.rs:237:33:
+Box<dyn Any>
+stack backtrace:
+   0:     0x7f19144772cb - std[d28b1718532fa52a]::panicking::begin_panic::<rustc_errors[5f6471fb26749c51]::ExplicitBug>
+",
+        )
+        .unwrap();
+        assert_eq!(report.kind, IceKind::Bug);
+        assert_eq!(
+            report.message.as_deref(),
+            Some("unexpected bound ty in binder: 0")
+        );
+        assert_eq!(
+            report.backtrace[0].symbol,
+            "std[d28b1718532fa52a]::panicking::begin_panic::<rustc_errors[5f6471fb26749c51]::ExplicitBug>"
+        );
+    }
+
+    #[test]
+    fn test_parse_delayed_bug() {
+        let report = IceReport::parse(
+            "note: no errors encountered even though delayed bugs were created
+
+error: internal compiler error: error performing operation: fully_perform
+  --> ice/100041.rs:14:5
+   |
+14 |     latent(&[]);
+   |     ^^^^^^^^^^^
+   = note: delayed at /rustc-dev/abc/compiler/rustc_trait_selection/src/traits/query/type_op/custom.rs:126:26
+         0: <rustc_errors::DiagCtxtInner>::emit_diagnostic
+         1: <rustc_errors::DiagCtxtHandle>::emit_diagnostic
+
+  --> ice/100041.rs:14:5
+",
+        )
+        .unwrap();
+        assert_eq!(report.kind, IceKind::DelayedBug);
+        assert_eq!(
+            report.message.as_deref(),
+            Some("error performing operation: fully_perform")
+        );
+        assert_eq!(report.location.as_ref().unwrap().line, 126);
+        assert_eq!(report.delayed.len(), 1);
+        assert_eq!(report.delayed[0].backtrace.len(), 2);
+        assert!(report.backtrace.is_empty());
+        assert!(report.query_stack.is_none());
+    }
+
+    #[test]
+    fn test_parse_delayed_bug_dump() {
+        let text = "\
+delayed bug: error performing operation: fully_perform
+   0: <rustc_errors::DiagCtxtInner>::emit_diagnostic
+   1: <rustc_errors::DiagCtxtHandle>::emit_diagnostic
+   2: <rustc_errors::DiagCtxtHandle>::span_delayed_bug::<rustc_span::span_encoding::Span, alloc::string::String>
+   3: <rustc_borrowck::type_check::TypeChecker as rustc_middle::mir::visit::Visitor>::visit_body
+   4: rustc_borrowck::type_check::type_check
+   5: rustc_borrowck::mir_borrowck
+
+
+
+rustc version: 1.97.0-nightly (e50aa6fba 2026-05-19)
+platform: x86_64-unknown-linux-gnu
+";
+        assert_eq!(IceKind::detect(text), Some(IceKind::DelayedBug));
+        let report = IceReport::parse(text).unwrap();
+        assert_eq!(
+            report.message.as_deref(),
+            Some("error performing operation: fully_perform")
+        );
+        assert!(report.location.is_none());
+        assert_eq!(report.delayed.len(), 1);
+        assert_eq!(report.delayed[0].backtrace.len(), 6);
+        assert_eq!(
+            report.functions().first().map(String::as_str),
+            Some(
+                "<rustc_borrowck::type_check::TypeChecker as rustc_middle::mir::visit::Visitor>::visit_body"
+            )
+        );
+        assert_eq!(IceReport::parse_all(text).len(), 1);
+    }
+
+    #[test]
+    fn test_parse_segfault() {
+        let text = "\
+error: rustc interrupted by SIGSEGV, printing backtrace
+
+/rustup/lib/librustc_driver-b5a9.so(+0x3c8e78c)[0x7f40d0a8e78c]
+/lib/x86_64-linux-gnu/libc.so.6(+0x3c050)[0x7f40ccc5a050]
+/rustup/lib/librustc_driver-b5a9.so(+0x56d88d7)[0x7f40d24d88d7]
+
+### cycle encountered after 1 frames with period 2
+/rustup/lib/librustc_driver-b5a9.so(+0x56da858)[0x7f40d24da858]
+/rustup/lib/librustc_driver-b5a9.so(+0x56d8915)[0x7f40d24d8915]
+### recursed 63 times
+
+note: rustc unexpectedly overflowed its stack! this is a bug
+";
+        let report = IceReport::parse(text).unwrap();
+        assert_eq!(report.kind, IceKind::Segfault);
+        assert_eq!(report.location, None);
+        assert_eq!(
+            report.signature(),
+            [
+                "librustc_driver-b5a9.so+0x56d88d7",
+                "librustc_driver-b5a9.so+0x56da858",
+                "librustc_driver-b5a9.so+0x56d8915",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_all() {
+        let text = "\
+error[E0425]: cannot find value `x` in this scope
+error: internal compiler error: compiler/rustc_middle/src/ty/fold.rs:237:33: unexpected bound ty
+thread 'rustc' (1) panicked at compiler/rustc_middle/src/util/bug.rs:36:26:
+Box<dyn Any>
+query stack during panic:
+#0 [typeck] type-checking `main`
+end of query stack
+note: no errors encountered even though delayed bugs were created
+
+error: internal compiler error: first
+note: delayed at compiler/rustc_borrowck/src/type_check/mod.rs:661:21
+
+error: internal compiler error: second
+note: delayed at compiler/rustc_hir_typeck/src/lib.rs:1:1
+
+query stack during panic:
+end of query stack
+";
+        let reports = IceReport::parse_all(text);
+        let kinds: Vec<_> = reports.iter().map(|r| r.kind).collect();
+        assert_eq!(
+            kinds,
+            [IceKind::Bug, IceKind::DelayedBug, IceKind::DelayedBug]
+        );
+        let messages: Vec<_> = reports.iter().map(|r| r.message.as_deref()).collect();
+        assert_eq!(
+            messages,
+            [Some("unexpected bound ty"), Some("first"), Some("second")]
+        );
+        assert!(reports.iter().all(|r| r.diagnostics.len() == 1));
+        assert_eq!(reports[1].query_stack, Some(Vec::new()));
+    }
+
+    #[test]
+    fn test_parse_not_an_ice() {
+        assert_eq!(
+            IceReport::parse("error[E0425]: cannot find value `x`\n"),
+            None
+        );
+    }
+}
@@ -1,36 +1,766 @@
-use std::{fs, path::Path, process};
+use std::{
+    borrow::Cow,
+    collections::BTreeMap,
+    env, fmt, fs,
+    io::{self, Read as _},
+    path::{Path, PathBuf},
+    process,
+    sync::{Arc, Mutex, mpsc},
+    thread,
+    time::{Duration, Instant},
+};
 
 use anyhow::Context;
-use tracing::debug;
-
-pub(crate) fn go(path: &Path) -> anyhow::Result<String> {
-    let temp_file = tempfile::NamedTempFile::new().context("failed to create temporary file")?;
-    let mut cmd = process::Command::new("rustc");
-    let mut cmd = cmd
-        .arg("+nightly")
-        .arg("--crate-name=drice")
-        .arg("--crate-type=lib")
-        .arg("--emit=mir")
-        .arg("-o")
-        .arg(temp_file.path());
+use tracing::{debug, warn};
+
+use crate::{
+    cache::Cache,
+    directive::{Aux, Directives},
+    project,
+};
+
+/// Which `rustc` to run
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Toolchain {
+    /// A rustup toolchain, e.g., `nightly-2025-06-01` or a linked `stage1`
+    Rustup(String),
+    /// Path to a `rustc` binary
+    Path(PathBuf),
+}
+
+impl Default for Toolchain {
+    fn default() -> Self {
+        Self::Rustup(String::from("nightly"))
+    }
+}
+
+impl fmt::Display for Toolchain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Rustup(name) => write!(f, "+{name}"),
+            Self::Path(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+/// Output of `rustc -vV`
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub(crate) struct Version {
+    pub(crate) release: String,
+    pub(crate) commit_hash: Option<String>,
+    pub(crate) commit_date: Option<String>,
+    pub(crate) host: String,
+}
+
+impl Version {
+    #[must_use]
+    fn parse(s: &str) -> Self {
+        let field = |name: &str| {
+            s.lines()
+                .find_map(|line| line.strip_prefix(name)?.strip_prefix(": "))
+                .map(str::to_owned)
+                .filter(|value| value != "unknown")
+        };
+        Self {
+            release: field("release").unwrap_or_default(),
+            commit_hash: field("commit-hash"),
+            commit_date: field("commit-date"),
+            host: field("host").unwrap_or_default(),
+        }
+    }
+}
+
+/// Like `rustc -V`
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "rustc {}", self.release)?;
+        if let Some(hash) = &self.commit_hash {
+            write!(f, " ({}", &hash[..hash.len().min(9)])?;
+            if let Some(date) = &self.commit_date {
+                write!(f, " {date}")?;
+            }
+            write!(f, ")")?;
+        }
+        Ok(())
+    }
+}
+
+/// Versions of toolchains, see [`Toolchain::cached_version`]
+static VERSIONS: Mutex<BTreeMap<Toolchain, Option<Version>>> = Mutex::new(BTreeMap::new());
+
+impl Toolchain {
+    /// Like [`Toolchain::version`], but only runs `rustc` once per toolchain.
+    pub(crate) fn cached_version(&self) -> Option<Version> {
+        let mut versions = VERSIONS.lock().expect("poisoned");
+        versions
+            .entry(self.clone())
+            .or_insert_with(|| match self.version() {
+                Ok(version) => Some(version),
+                Err(e) => {
+                    warn!("failed to get version of rustc ({self}): {e:#}");
+                    None
+                }
+            })
+            .clone()
+    }
+
+    pub(crate) fn version(&self) -> anyhow::Result<Version> {
+        let output = self
+            .command()
+            .arg("-vV")
+            .output()
+            .with_context(|| format!("failed to execute rustc ({self})"))?;
+        if !output.status.success() {
+            anyhow::bail!(
+                "rustc ({self}) -vV failed: {}",
+                String::from_utf8_lossy(&output.stderr)
+            );
+        }
+        Ok(Version::parse(&String::from_utf8_lossy(&output.stdout)))
+    }
+
+    pub(crate) fn command(&self) -> process::Command {
+        match self {
+            Self::Rustup(name) => {
+                let mut cmd = process::Command::new("rustc");
+                cmd.arg(format!("+{name}"));
+                cmd
+            }
+            Self::Path(path) => process::Command::new(path),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct RustcConfig {
+    pub(crate) toolchain: Toolchain,
+    /// Kill `rustc` after this long
+    pub(crate) timeout: Option<Duration>,
+    /// Limit on the address space of `rustc`, in bytes
+    pub(crate) memory_limit: Option<u64>,
+    pub(crate) cache: Option<Cache>,
+}
+
+impl RustcConfig {
+    /// This config without the cache, to save outputs: cached outputs may be
+    /// of the same source at another path, see [`Cache::key`]
+    #[must_use]
+    pub(crate) fn uncached(&self) -> Self {
+        Self {
+            cache: None,
+            ..self.clone()
+        }
+    }
+}
+
+/// How `rustc` exited
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Exit {
+    Code(i32),
+    Signal(i32),
+    Timeout,
+}
+
+impl From<process::ExitStatus> for Exit {
+    fn from(status: process::ExitStatus) -> Self {
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::ExitStatusExt as _;
+            if let Some(signal) = status.signal() {
+                return Self::Signal(signal);
+            }
+        }
+        Self::Code(status.code().unwrap_or(-1))
+    }
+}
+
+impl fmt::Display for Exit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Code(code) => write!(f, "exit code {code}"),
+            Self::Signal(signal) => write!(f, "signal {signal}"),
+            Self::Timeout => write!(f, "timeout"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub(crate) struct Output {
+    pub(crate) stderr: String,
+    pub(crate) exit: Exit,
+    /// Contents of the `rustc-ice-*.txt` file, which has the full backtrace
+    #[serde(default)]
+    pub(crate) ice_dump: Option<String>,
+    #[serde(default)]
+    pub(crate) version: Option<Version>,
+    /// The program and its arguments, see [`recorded_command`]
+    #[serde(default)]
+    pub(crate) command: Vec<String>,
+    /// Environment variables that affect the output, see [`recorded_env`]
+    #[serde(default)]
+    pub(crate) env: BTreeMap<String, String>,
+    /// The crate that failed to compile, for reproducers with several crates
+    #[serde(default)]
+    pub(crate) krate: Option<String>,
+    /// The limit on the address space that `rustc` ran with, see
+    /// [`RustcConfig::memory_limit`]
+    #[serde(default)]
+    pub(crate) memory_limit: Option<u64>,
+}
+
+/// How a snapshot of rustc's output was produced.
+///
+/// Stored next to `NNN.out` as `NNN.run.json`.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub(crate) struct Invocation {
+    pub(crate) version: Option<Version>,
+    pub(crate) command: Vec<String>,
+    #[serde(default)]
+    pub(crate) env: BTreeMap<String, String>,
+    pub(crate) exit: Exit,
+}
+
+impl Invocation {
+    /// Path of the invocation for the snapshot at `out`
+    #[must_use]
+    pub(crate) fn path(out: &Path) -> PathBuf {
+        out.with_extension("run.json")
+    }
+
+    /// Read the invocation for the snapshot at `out`, if there is one
+    pub(crate) fn read(out: &Path) -> anyhow::Result<Option<Self>> {
+        let path = Self::path(out);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("failed to read file: {}", path.display()))?;
+        let invocation = serde_json::from_str(&content)
+            .with_context(|| format!("failed to parse file: {}", path.display()))?;
+        Ok(Some(invocation))
+    }
+
+    /// Write the invocation for the snapshot at `out`
+    pub(crate) fn write(&self, out: &Path) -> anyhow::Result<()> {
+        let path = Self::path(out);
+        let content =
+            serde_json::to_string_pretty(self).context("failed to serialize invocation")?;
+        fs::write(&path, content + "\n")
+            .with_context(|| format!("failed to write file: {}", path.display()))
+    }
+}
+
+impl fmt::Display for Invocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.version {
+            Some(version) => write!(f, "{version} on {}", version.host)?,
+            None => write!(f, "unknown rustc")?,
+        }
+        write!(f, ", {}: `", self.exit)?;
+        for (name, value) in &self.env {
+            write!(f, "{name}={value} ")?;
+        }
+        write!(f, "{}`", self.command.join(" "))
+    }
+}
+
+/// SIGKILL, as sent by the Linux OOM killer (among others)
+const SIGKILL: i32 = 9;
+
+/// Separates stderr from the ICE dump in [`Output::text`]
+pub(crate) const ICE_DUMP_HEADER: &str = "note: drice: contents of rustc-ice file:";
+
+impl Output {
+    #[must_use]
+    pub(crate) fn invocation(&self) -> Invocation {
+        Invocation {
+            version: self.version.clone(),
+            command: self.command.clone(),
+            env: self.env.clone(),
+            exit: self.exit,
+        }
+    }
+
+    /// stderr, followed by the ICE dump (if any)
+    #[must_use]
+    pub(crate) fn text(&self) -> Cow<'_, str> {
+        match &self.ice_dump {
+            None => Cow::Borrowed(&self.stderr),
+            Some(dump) => Cow::Owned(format!("{}\n{ICE_DUMP_HEADER}\n{dump}", self.stderr)),
+        }
+    }
+
+    /// Whether `rustc` ran out of memory: it failed to allocate, or it was
+    /// killed while running with `--memory-limit`. Without a limit, a kill may
+    /// be of the user or of a parent cgroup, so it doesn't count.
+    #[must_use]
+    pub(crate) fn out_of_memory(&self) -> bool {
+        (self.memory_limit.is_some() && self.exit == Exit::Signal(SIGKILL))
+            || self.stderr.lines().any(|line| {
+                (line.starts_with("memory allocation of ") && line.ends_with(" failed"))
+                    || line.starts_with("LLVM ERROR: out of memory")
+            })
+    }
+}
+
+/// An output of `rustc` exiting with code 101, for tests
+#[cfg(test)]
+#[must_use]
+pub(crate) fn output(stderr: &str) -> Output {
+    Output {
+        stderr: stderr.to_owned(),
+        exit: Exit::Code(101),
+        ice_dump: None,
+        version: None,
+        command: Vec::new(),
+        env: BTreeMap::new(),
+        krate: None,
+        memory_limit: None,
+    }
+}
+
+#[cfg(unix)]
+fn limit_memory(cmd: &mut process::Command, bytes: u64) {
+    use std::os::unix::process::CommandExt as _;
+    let limit = libc::rlimit {
+        rlim_cur: bytes,
+        rlim_max: bytes,
+    };
+    // SAFETY: `setrlimit` is async-signal-safe and `limit` is `Copy`.
+    unsafe {
+        cmd.pre_exec(move || {
+            if libc::setrlimit(libc::RLIMIT_AS, &raw const limit) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+}
+
+#[cfg(not(unix))]
+fn limit_memory(_cmd: &mut process::Command, _bytes: u64) {
+    warn!("memory limits are not supported on this platform");
+}
+
+/// Run `cmd` in a process group of its own, so that [`kill`] also gets the
+/// processes it starts, like `rustc` under `cargo`
+#[cfg(unix)]
+fn own_process_group(cmd: &mut process::Command) {
+    use std::os::unix::process::CommandExt as _;
+    cmd.process_group(0);
+}
+
+#[cfg(not(unix))]
+fn own_process_group(_cmd: &mut process::Command) {}
+
+/// Kill `child` and the processes it started, see [`own_process_group`]
+#[cfg(unix)]
+fn kill(child: &mut process::Child) -> io::Result<()> {
+    let pgid = libc::pid_t::try_from(child.id()).map_err(io::Error::other)?;
+    // SAFETY: `killpg` has no memory safety requirements.
+    if unsafe { libc::killpg(pgid, libc::SIGKILL) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn kill(child: &mut process::Child) -> io::Result<()> {
+    child.kill()
+}
+
+/// How long to wait for stderr to close after killing `rustc`
+const KILL_GRACE: Duration = Duration::from_secs(1);
+
+/// Run `cmd` to completion or until `timeout` elapses, collecting stderr.
+fn run(mut cmd: process::Command, timeout: Option<Duration>) -> io::Result<Output> {
+    own_process_group(&mut cmd);
+    let mut child = cmd
+        .stdin(process::Stdio::null())
+        .stdout(process::Stdio::null())
+        .stderr(process::Stdio::piped())
+        .spawn()?;
+    let mut stderr = child.stderr.take().expect("stderr is piped");
+    // Read on another thread so that rustc can't block on a full pipe
+    let buf = Arc::new(Mutex::new(Vec::new()));
+    let (done_tx, done_rx) = mpsc::channel();
+    {
+        let buf = Arc::clone(&buf);
+        thread::spawn(move || {
+            let mut chunk = [0; 4096];
+            loop {
+                match stderr.read(&mut chunk) {
+                    Ok(0) => break,
+                    Ok(n) => buf.lock().expect("poisoned").extend_from_slice(&chunk[..n]),
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                    Err(e) => {
+                        warn!("failed to read stderr of rustc: {e}");
+                        break;
+                    }
+                }
+            }
+            let _ = done_tx.send(());
+        });
+    }
+    let deadline = timeout.map(|t| Instant::now() + t);
+    let exit = loop {
+        if let Some(status) = child.try_wait()? {
+            break Exit::from(status);
+        }
+        if deadline.is_some_and(|d| Instant::now() >= d) {
+            kill(&mut child)?;
+            child.wait()?;
+            break Exit::Timeout;
+        }
+        thread::sleep(Duration::from_millis(10));
+    };
+    if exit == Exit::Timeout {
+        // Descendants of rustc may still be holding the pipe open
+        if done_rx.recv_timeout(KILL_GRACE).is_err() {
+            warn!("stderr of rustc may be truncated");
+        }
+    } else {
+        let _ = done_rx.recv();
+    }
+    let stderr = buf.lock().expect("poisoned");
+    Ok(Output {
+        stderr: String::from_utf8_lossy(&stderr).into_owned(),
+        exit,
+        ice_dump: None,
+        version: None,
+        command: Vec::new(),
+        env: BTreeMap::new(),
+        krate: None,
+        memory_limit: None,
+    })
+}
+
+/// Read the `rustc-ice-*.txt` file(s) in `dir`
+fn read_ice_dump(dir: &Path) -> anyhow::Result<Option<String>> {
+    let mut paths = Vec::new();
+    for entry in
+        fs::read_dir(dir).with_context(|| format!("failed to read directory: {}", dir.display()))?
+    {
+        let path = entry
+            .with_context(|| format!("failed to read entry in directory: {}", dir.display()))?
+            .path();
+        if let Some(name) = path.file_name().and_then(|s| s.to_str())
+            && name.starts_with("rustc-ice-")
+            && name.ends_with(".txt")
+        {
+            paths.push(path);
+        }
+    }
+    if paths.is_empty() {
+        return Ok(None);
+    }
+    paths.sort();
+    let mut dump = String::new();
+    for path in paths {
+        dump.push_str(
+            &fs::read_to_string(&path)
+                .with_context(|| format!("failed to read file: {}", path.display()))?,
+        );
+    }
+    Ok(Some(dump))
+}
+
+/// `arg` with the temporary directory (a new one for each run) replaced by
+/// `$TMPDIR`, like `$TMPDIR/ice` for `/tmp/.tmpAbC123/ice`
+fn without_temp_dir(arg: &str) -> String {
+    let temp = env::temp_dir();
+    let Some(rest) = Path::new(arg)
+        .strip_prefix(&temp)
+        .ok()
+        .filter(|rest| rest.components().next().is_some())
+    else {
+        return arg.to_owned();
+    };
+    // Skip the random name of the directory or file made by `tempfile`
+    let rest: PathBuf = rest.components().skip(1).collect();
+    if rest.as_os_str().is_empty() {
+        String::from("$TMPDIR")
+    } else {
+        format!("$TMPDIR/{}", rest.display())
+    }
+}
+
+/// The program and arguments of `cmd`, to record in [`Invocation`]: without
+/// the output file, which is temporary and discarded, and without temporary
+/// directories, see [`without_temp_dir`]
+fn recorded_command(cmd: &process::Command) -> Vec<String> {
+    let mut command = vec![cmd.get_program().to_string_lossy().into_owned()];
+    let mut args = cmd.get_args().map(|arg| arg.to_string_lossy());
+    while let Some(arg) = args.next() {
+        if arg == "-o" {
+            args.next();
+        } else {
+            command.push(without_temp_dir(&arg));
+        }
+    }
+    command
+}
+
+/// Environment variables of `rustc` (and `cargo`) that change its output,
+/// besides those that [`execute`] sets
+const INHERITED_ENV: &[&str] = &["RUSTFLAGS", "RUST_MIN_STACK", "RUSTC_BOOTSTRAP"];
+
+/// The environment variables of `cmd` to record in [`Invocation`]: those set
+/// for it, and those in [`INHERITED_ENV`] that it inherits
+fn recorded_env(cmd: &process::Command) -> BTreeMap<String, String> {
+    let mut recorded: BTreeMap<_, _> = INHERITED_ENV
+        .iter()
+        .filter_map(|&name| Some((name.to_owned(), env::var(name).ok()?)))
+        .collect();
+    for (name, value) in cmd.get_envs() {
+        let name = name.to_string_lossy().into_owned();
+        match value {
+            Some(value) => recorded.insert(name, without_temp_dir(&value.to_string_lossy())),
+            None => recorded.remove(&name),
+        };
+    }
+    recorded
+}
+
+/// Run `cmd` (`rustc`, or `cargo` running `rustc`) with the limits in
+/// `config`, collecting the ICE dump.
+pub(crate) fn execute(
+    config: &RustcConfig,
+    mut cmd: process::Command,
+    path: &Path,
+) -> anyhow::Result<Output> {
+    // Keep rustc-ice-*.txt out of the working directory
+    let ice_dir = tempfile::tempdir().context("failed to create temporary directory")?;
+    cmd.env("RUSTC_ICE", ice_dir.path());
+    cmd.env("RUST_BACKTRACE", "1");
+    if let Some(bytes) = config.memory_limit {
+        limit_memory(&mut cmd, bytes);
+    }
+    debug!("command: {cmd:?}");
+    let command = recorded_command(&cmd);
+    let env = recorded_env(&cmd);
+    let mut output = run(cmd, config.timeout).with_context(|| {
+        format!(
+            "failed to execute rustc ({}) for: {}",
+            config.toolchain,
+            path.display()
+        )
+    })?;
+    if output.exit == Exit::Timeout {
+        warn!("{}: rustc timed out", path.display());
+    }
+    output.ice_dump = read_ice_dump(ice_dir.path())?;
+    output.version = config.toolchain.cached_version();
+    output.command = command;
+    output.env = env;
+    output.memory_limit = config.memory_limit;
+    Ok(output)
+}
+
+/// Look up `key` in the cache, or run `f` and cache its output
+pub(crate) fn cached(
+    config: &RustcConfig,
+    key: &str,
+    f: impl FnOnce() -> anyhow::Result<Output>,
+) -> anyhow::Result<Output> {
+    if let Some(cache) = &config.cache
+        && let Some(output) = cache.get(&config.toolchain, key)
+    {
+        return Ok(output);
+    }
+    let output = f()?;
+    if let Some(cache) = &config.cache {
+        // Caching is an optimization, so don't fail the run over it
+        if let Err(e) = cache.put(&config.toolchain, key, &output) {
+            warn!("failed to cache rustc output: {e:#}");
+        }
+    }
+    Ok(output)
+}
+
+/// Path of an auxiliary crate of `path`, like compiletest
+fn aux_path(path: &Path, aux: &Aux) -> PathBuf {
+    path.parent()
+        .unwrap_or(Path::new(""))
+        .join("auxiliary")
+        .join(&aux.file)
+}
+
+/// Append the sources of the auxiliary crates of `path` (recursively) to `out`
+fn aux_sources(path: &Path, directives: &Directives, out: &mut String) -> anyhow::Result<()> {
+    for aux in &directives.aux {
+        let aux_path = aux_path(path, aux);
+        let content = fs::read_to_string(&aux_path)
+            .with_context(|| format!("failed to read auxiliary crate: {}", aux_path.display()))?;
+        out.push_str(&content);
+        aux_sources(&aux_path, &Directives::parse(&content), out)?;
+    }
+    Ok(())
+}
+
+/// Build the auxiliary crates of `path` (recursively) into `out_dir`.
+///
+/// Returns the output of `rustc` on the first one that fails to compile.
+fn build_aux(
+    config: &RustcConfig,
+    path: &Path,
+    directives: &Directives,
+    out_dir: &Path,
+) -> anyhow::Result<Option<Output>> {
+    for aux in &directives.aux {
+        let aux_path = aux_path(path, aux);
+        let content = fs::read_to_string(&aux_path)
+            .with_context(|| format!("failed to read auxiliary crate: {}", aux_path.display()))?;
+        let aux_directives = Directives::parse(&content);
+        if let Some(output) = build_aux(config, &aux_path, &aux_directives, out_dir)? {
+            return Ok(Some(output));
+        }
+        let mut cmd = config.toolchain.command();
+        cmd.arg(format!("--crate-name={}", aux.crate_name))
+            .arg(if aux.proc_macro {
+                "--crate-type=proc-macro"
+            } else {
+                "--crate-type=lib"
+            })
+            .arg("--out-dir")
+            .arg(out_dir)
+            .args(extern_args(&aux_directives, out_dir))
+            .args(aux_directives.args())
+            .arg(&aux_path);
+        let mut output = execute(config, cmd, &aux_path)?;
+        if output.exit != Exit::Code(0) {
+            debug!("{}: failed to compile", aux_path.display());
+            output.krate = Some(aux.crate_name.clone());
+            return Ok(Some(output));
+        }
+    }
+    Ok(None)
+}
+
+/// Arguments to find the auxiliary crates built into `out_dir`
+fn extern_args(directives: &Directives, out_dir: &Path) -> Vec<String> {
+    if directives.aux.is_empty() {
+        return Vec::new();
+    }
+    let mut args = vec![String::from("-L"), out_dir.display().to_string()];
+    for aux in &directives.aux {
+        if aux.is_extern {
+            args.push(format!("--extern={}", aux.crate_name));
+        }
+    }
+    args
+}
+
+/// Run `rustc` on `path`, which is a Rust source file or a directory with a
+/// reproducer, see [`project`].
+pub(crate) fn go(config: &RustcConfig, path: &Path) -> anyhow::Result<Output> {
+    if project::is_cargo(path) {
+        return project::cargo(config, path);
+    }
+    if path.is_dir() {
+        return go(config, &project::main_file(path)?);
+    }
     let content = fs::read_to_string(path)
         .with_context(|| format!("failed to read file: {}", path.display()))?;
-    if content.contains("// @compile-flags: --edition=2024") {
-        cmd = cmd.arg("--edition=2024");
+    let directives = Directives::parse(&content);
+    for gate in directives.unsatisfied_gates() {
+        warn!("{}: may not reproduce, requires `{gate}`", path.display());
+    }
+    let mut args = vec![
+        String::from("--crate-name=drice"),
+        String::from("--crate-type=lib"),
+        String::from("--emit=mir"),
+    ];
+    args.extend(directives.args());
+
+    let mut sources = content;
+    aux_sources(path, &directives, &mut sources)?;
+    cached(config, &Cache::key(&sources, &args), || {
+        let aux_dir = tempfile::tempdir().context("failed to create temporary directory")?;
+        if let Some(output) = build_aux(config, path, &directives, aux_dir.path())? {
+            return Ok(output);
+        }
+        let temp_file =
+            tempfile::NamedTempFile::new().context("failed to create temporary file")?;
+        let mut cmd = config.toolchain.command();
+        cmd.arg("-o")
+            .arg(temp_file.path())
+            .args(&args)
+            .args(extern_args(&directives, aux_dir.path()))
+            .arg(path);
+        execute(config, cmd, path)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use super::{Exit, recorded_command, recorded_env, run, without_temp_dir};
+
+    #[cfg(unix)]
+    #[test]
+    fn test_timeout_kills_descendants() {
+        use std::{
+            fs, thread,
+            time::{Duration, Instant},
+        };
+
+        let dir = tempfile::tempdir().unwrap();
+        let pid_file = dir.path().join("pid");
+        // Like `cargo` running `rustc`
+        let mut cmd = process::Command::new("sh");
+        cmd.arg("-c")
+            .arg(r#"sleep 60 & echo $! > "$1"; wait"#)
+            .arg("sh")
+            .arg(&pid_file);
+        let output = run(cmd, Some(Duration::from_millis(500))).unwrap();
+        assert_eq!(output.exit, Exit::Timeout);
+        let pid: libc::pid_t = fs::read_to_string(&pid_file)
+            .unwrap()
+            .trim()
+            .parse()
+            .unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        // SAFETY: signal 0 only checks whether the process exists.
+        while unsafe { libc::kill(pid, 0) } == 0 {
+            assert!(Instant::now() < deadline, "sleep {pid} is still running");
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn test_recorded_command() {
+        let temp = env::temp_dir().join(".tmpAbC123");
+        let mut cmd = process::Command::new("rustc");
+        cmd.arg("+nightly")
+            .arg("-o")
+            .arg(&temp)
+            .arg("--crate-type=lib")
+            .arg("-L")
+            .arg(&temp)
+            .arg("ice/1.rs")
+            .env("RUSTC_ICE", temp.join("ice"))
+            .env("RUST_BACKTRACE", "1");
+        assert_eq!(
+            recorded_command(&cmd),
+            [
+                "rustc",
+                "+nightly",
+                "--crate-type=lib",
+                "-L",
+                "$TMPDIR",
+                "ice/1.rs"
+            ]
+        );
+        let env = recorded_env(&cmd);
+        assert_eq!(env["RUSTC_ICE"], "$TMPDIR/ice");
+        assert_eq!(env["RUST_BACKTRACE"], "1");
+        assert_eq!(without_temp_dir("ice/1.rs"), "ice/1.rs");
+        assert_eq!(
+            without_temp_dir(&env::temp_dir().to_string_lossy()),
+            env::temp_dir().to_string_lossy()
+        );
     }
-    cmd = cmd.arg(path);
-    debug!("command: {cmd:?}");
-    let output = cmd
-        // .env("RUSTC_ICE", "0")
-        .env("RUST_BACKTRACE", "1")
-        .stderr(process::Stdio::piped())
-        .output()
-        .with_context(|| {
-            format!(
-                "failed to execute rustc command for file: {}",
-                path.display()
-            )
-        })?;
-    let stderr = String::from_utf8_lossy(&output.stderr);
-    Ok(stderr.to_string())
 }
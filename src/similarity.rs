@@ -0,0 +1,202 @@
+//! Scored similarity between ICEs, for ranking known ICEs by how close they
+//! are to a new one.
+//!
+//! Unlike [`crate::check::same_report`], this never says yes or no, every
+//! component is a score between 0 and 1.
+
+use std::{collections::BTreeSet, fmt};
+
+use crate::report::IceReport;
+
+/// How similar two ICEs are, component by component
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Similarity {
+    pub(crate) location: f64,
+    pub(crate) message: f64,
+    pub(crate) query_stack: f64,
+    pub(crate) backtrace: f64,
+    pub(crate) diagnostics: f64,
+}
+
+/// Backtrace frames to compare, starting from where the ICE originated
+const BACKTRACE_FRAMES: usize = 10;
+
+/// Score of a component that both ICEs lack, which says little either way
+const BOTH_MISSING: f64 = 0.5;
+
+/// `2 * LCS / (|l| + |r|)`, or 1 if both are empty
+fn sequence_similarity<T: PartialEq>(l: &[T], r: &[T]) -> f64 {
+    if l.is_empty() && r.is_empty() {
+        return 1.0;
+    }
+    let mut prev = vec![0_usize; r.len() + 1];
+    let mut cur = vec![0_usize; r.len() + 1];
+    for x in l {
+        for (j, y) in r.iter().enumerate() {
+            cur[j + 1] = if x == y {
+                prev[j] + 1
+            } else {
+                cur[j].max(prev[j + 1])
+            };
+        }
+        std::mem::swap(&mut prev, &mut cur);
+    }
+    ratio(2 * prev[r.len()], l.len() + r.len())
+}
+
+/// Jaccard index, or 1 if both are empty
+fn set_similarity<T: Ord>(l: &BTreeSet<T>, r: &BTreeSet<T>) -> f64 {
+    if l.is_empty() && r.is_empty() {
+        return 1.0;
+    }
+    ratio(l.intersection(r).count(), l.union(r).count())
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn ratio(n: usize, d: usize) -> f64 {
+    n as f64 / d as f64
+}
+
+fn location(l: &IceReport, r: &IceReport) -> f64 {
+    let (Some(l_loc), Some(r_loc)) = (&l.location, &r.location) else {
+        return 0.0;
+    };
+    if l_loc.source_path() != r_loc.source_path() {
+        return if l_loc.crate_name().is_some() && l_loc.crate_name() == r_loc.crate_name() {
+            0.1
+        } else {
+            0.0
+        };
+    }
+    if (l_loc.line, l_loc.column) == (r_loc.line, r_loc.column) {
+        return 1.0;
+    }
+    let l_fns = l.functions();
+    let r_fns = r.functions();
+    match (l_fns.first(), r_fns.first()) {
+        (Some(l_fn), Some(r_fn)) if l_fn == r_fn => {
+            if l_fns.get(1) == r_fns.get(1) {
+                0.9
+            } else {
+                0.7
+            }
+        }
+        _ => 0.4,
+    }
+}
+
+fn message(l: &IceReport, r: &IceReport) -> f64 {
+    match (l.normalized_message(), r.normalized_message()) {
+        (None, None) => BOTH_MISSING,
+        (Some(l), Some(r)) => set_similarity(
+            &l.split_whitespace().collect(),
+            &r.split_whitespace().collect(),
+        ),
+        _ => 0.0,
+    }
+}
+
+fn query_stack(l: &IceReport, r: &IceReport) -> f64 {
+    match (&l.query_stack, &r.query_stack) {
+        (None, None) => BOTH_MISSING,
+        (Some(l), Some(r)) => sequence_similarity(
+            &l.iter().map(|f| &f.query).collect::<Vec<_>>(),
+            &r.iter().map(|f| &f.query).collect::<Vec<_>>(),
+        ),
+        _ => 0.0,
+    }
+}
+
+fn backtrace(l: &IceReport, r: &IceReport) -> f64 {
+    let mut l_fns = l.functions();
+    let mut r_fns = r.functions();
+    l_fns.truncate(BACKTRACE_FRAMES);
+    r_fns.truncate(BACKTRACE_FRAMES);
+    sequence_similarity(&l_fns, &r_fns)
+}
+
+fn diagnostics(l: &IceReport, r: &IceReport) -> f64 {
+    // Codes are more stable than messages, which mention names
+    let key = |report: &IceReport| {
+        report
+            .diagnostics
+            .iter()
+            .filter(|d| d.level == "error")
+            .map(|d| d.code.clone().unwrap_or_else(|| d.message.clone()))
+            .collect::<BTreeSet<_>>()
+    };
+    let (l, r) = (key(l), key(r));
+    if l.is_empty() && r.is_empty() {
+        return BOTH_MISSING;
+    }
+    set_similarity(&l, &r)
+}
+
+impl Similarity {
+    #[must_use]
+    pub(crate) fn new(l: &IceReport, r: &IceReport) -> Self {
+        Self {
+            location: location(l, r),
+            message: message(l, r),
+            query_stack: query_stack(l, r),
+            backtrace: backtrace(l, r),
+            diagnostics: diagnostics(l, r),
+        }
+    }
+
+    /// Weighted average of the components
+    #[must_use]
+    pub(crate) fn score(&self) -> f64 {
+        0.35 * self.location
+            + 0.2 * self.message
+            + 0.2 * self.query_stack
+            + 0.15 * self.backtrace
+            + 0.1 * self.diagnostics
+    }
+}
+
+impl fmt::Display for Similarity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:.2} (location {:.2}, message {:.2}, query stack {:.2}, backtrace {:.2}, diagnostics {:.2})",
+            self.score(),
+            self.location,
+            self.message,
+            self.query_stack,
+            self.backtrace,
+            self.diagnostics
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Similarity, sequence_similarity};
+    use crate::{ice::ICES, report::IceReport};
+
+    #[test]
+    fn test_sequence_similarity() {
+        assert!((sequence_similarity::<u8>(&[], &[]) - 1.0).abs() < f64::EPSILON);
+        assert!((sequence_similarity(&[1, 2, 3], &[1, 3]) - 0.8).abs() < f64::EPSILON);
+        assert!(sequence_similarity(&[1], &[2]).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn test_similarity_self_ranks_first() {
+        let reports: Vec<_> = ICES
+            .iter()
+            .filter_map(|(nm, content)| Some((nm, IceReport::parse(content)?)))
+            .collect();
+        for (nm, report) in &reports {
+            let own = Similarity::new(report, report).score();
+            for (other, other_report) in &reports {
+                let score = Similarity::new(report, other_report).score();
+                assert!(
+                    score <= own,
+                    "{nm} is more similar to {other} ({score:.2}) than to itself ({own:.2})"
+                );
+            }
+        }
+    }
+}